@@ -1,3 +1,4 @@
+use rustycore::Central;
 use rustycore::api::{
     central::{CentralManager, ScanFilter},
     central_event::CentralEvent,
@@ -18,20 +19,17 @@ async fn main() {
 async fn setup_central_manager() {
     let (sender_tx, mut receiver_rx) = mpsc::channel::<CentralEvent>(256);
 
-    let mut central_manager = CentralManager::new(sender_tx).await.unwrap();
-    
+    let mut central_manager = Central::new(sender_tx).await.unwrap();
+
     // start scanning for devices
     central_manager.start_scan(ScanFilter::default()).await.unwrap();
     // Handle Updates
-        while let Some(event) = receiver_rx.recv().await {
-            handle_updates(event);
-        }
-    log::info!("Log");
+    while let Some(event) = receiver_rx.recv().await {
+        handle_updates(event);
+    }
 }
 
 /// Listen to all updates and respond if require
 pub fn handle_updates(update: CentralEvent) {
-    match update {
-        _ => todo!()
-    }
+    log::info!("{:?}", update);
 }
@@ -1,8 +1,11 @@
+use rustycore::{Central, Peripheral};
 use rustycore::api::{
     central::{CentralManager, ScanFilter},
-    central_event::CentralEvent, peripheral_event::PeripheralEvent,
+    central_event::CentralEvent,
+    peripheral::PeripheralManager,
+    peripheral_event::PeripheralEvent,
 };
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, Sender};
 use log::LevelFilter;
 
 
@@ -12,59 +15,62 @@ async fn main() {
         .filter_level(LevelFilter::Info)
         .init();
 
-     
     let (sender_tx, mut receiver_rx) = mpsc::channel::<CustomEventEnum>(256);
-    setup_central_manager(sender_tx.clone()).await;
-    setup_peripheral_manager(sender_tx).await;
+    // Both managers are kept alive for as long as events are handled
+    let _central_manager = setup_central_manager(sender_tx.clone()).await;
+    let _peripheral_manager = setup_peripheral_manager(sender_tx).await;
+    while let Some(event) = receiver_rx.recv().await {
+        log::info!("{:?}", event);
+    }
 }
 
-async fn setup_central_manager(api_event_tx: Sender<CustomEventEnum>) {
+async fn setup_central_manager(api_event_tx: Sender<CustomEventEnum>) -> Central {
     let (sender_tx, mut receiver_rx) = mpsc::channel::<CentralEvent>(256);
 
-    let mut central_manager = CentralManager::new(sender_tx).await.unwrap();
-    
+    let mut central_manager = Central::new(sender_tx).await.unwrap();
+
     // start scanning for devices
     central_manager.start_scan(ScanFilter::default()).await.unwrap();
     // Handle Updates
     tokio::spawn(async move {
         while let Some(event) = receiver_rx.recv().await {
-            handle_central_updates(event, api_event_tx);
+            handle_central_updates(event, &api_event_tx).await;
         }
     });
-    log::info!("Log");
+    central_manager
 }
 
-async fn setup_peripheral_manager(api_event_tx: Sender<CustomEventEnum>) {
+async fn setup_peripheral_manager(api_event_tx: Sender<CustomEventEnum>) -> Peripheral {
     let (sender_tx, mut receiver_rx) = mpsc::channel::<PeripheralEvent>(256);
 
-    let mut peripheral_manager = PeripheralManager::new(sender_tx).await.unwrap();
-    
+    let mut peripheral_manager = Peripheral::new(sender_tx).await.unwrap();
+
     // start advertising for centrals to connect
-    peripheral_manager.start_advertising("user_name", );
-    
+    if let Err(e) = peripheral_manager.start_advertising(Some("user_name"), &[]).await {
+        log::error!("Failed to start advertising: {}", e);
+    }
+
     // Handle Updates
     tokio::spawn(async move {
         while let Some(event) = receiver_rx.recv().await {
-            handle_peripheral_updates(event, api_event_tx);
+            handle_peripheral_updates(event, &api_event_tx).await;
         }
     });
-    log::info!("Log");
+    peripheral_manager
 }
 
 /// Listen to all updates and respond if require
-pub fn handle_central_updates(update: CentralEvent, tx: Sender<CustomEventEnum>) {
-    match update {
-        _ => todo!()
-    }
+pub async fn handle_central_updates(update: CentralEvent, tx: &Sender<CustomEventEnum>) {
+    let _ = tx.send(CustomEventEnum::Central(update)).await;
 }
 
 /// Listen to all updates and respond if require
-pub fn handle_peripheral_updates(update: PeripheralEvent, tx: Sender<CustomEventEnum>) {
-    match update {
-        _ => todo!()
-    }
+pub async fn handle_peripheral_updates(update: PeripheralEvent, tx: &Sender<CustomEventEnum>) {
+    let _ = tx.send(CustomEventEnum::Peripheral(update)).await;
 }
 
-enum CustomEventEnum {
-
+#[derive(Debug)]
+pub enum CustomEventEnum {
+    Central(CentralEvent),
+    Peripheral(PeripheralEvent),
 }
@@ -0,0 +1,111 @@
+// Loopback between a central and the echo test service. Run it on two machines, or on one when
+// the adapter allows connecting to itself: the peripheral side echoes every write as a
+// notification and the central side prints what comes back.
+use std::time::Duration;
+
+use futures::StreamExt;
+use log::LevelFilter;
+use rustycore::api::{
+    central::{CentralManager, PeripheralRemote, ScanFilter},
+    central_event::CentralEvent,
+    characteristic::{Characteristic, CharacteristicWriteType},
+    peripheral::PeripheralManager,
+    peripheral_event::{PeripheralEvent, RequestResponse, WriteRequestResponse},
+    service::{ECHO_CHARACTERISTIC_UUID, ECHO_SERVICE_UUID, Service},
+};
+use rustycore::{Central, Peripheral};
+use tokio::sync::mpsc;
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::formatted_builder()
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    tokio::spawn(run_echo_peripheral());
+    run_central().await;
+}
+
+async fn run_echo_peripheral() {
+    let (sender_tx, mut receiver_rx) = mpsc::channel::<PeripheralEvent>(256);
+    let mut peripheral = Peripheral::new(sender_tx).await.unwrap();
+    peripheral
+        .wait_until_powered_on(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    peripheral.add_service(&Service::echo_test()).await.unwrap();
+    peripheral
+        .start_advertising(Some("rustycore-echo"), &[ECHO_SERVICE_UUID])
+        .await
+        .unwrap();
+
+    while let Some(event) = receiver_rx.recv().await {
+        if let PeripheralEvent::WriteRequest { id, request, value, .. } = event {
+            let response = if request.characteristic == ECHO_CHARACTERISTIC_UUID {
+                RequestResponse::Success
+            } else {
+                RequestResponse::WriteNotPermitted
+            };
+            let echo = response == RequestResponse::Success;
+            peripheral
+                .respond_to_request(id, WriteRequestResponse { response }.into())
+                .await
+                .unwrap();
+            if echo {
+                peripheral
+                    .update_characteristic(ECHO_CHARACTERISTIC_UUID, value)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+}
+
+async fn run_central() {
+    let (sender_tx, mut receiver_rx) = mpsc::channel::<CentralEvent>(256);
+    let mut central = Central::new(sender_tx).await.unwrap();
+    central
+        .wait_until_powered_on(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    central
+        .start_scan(ScanFilter {
+            services: vec![ECHO_SERVICE_UUID],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let server = loop {
+        if let Some(CentralEvent::DeviceDiscovered { server, .. }) = receiver_rx.recv().await {
+            break server;
+        }
+    };
+    central.stop_scan().await.unwrap();
+
+    let peripheral = central.peripheral(&server.into()).await.unwrap();
+    let mut notifications = peripheral
+        .connect_subscribe(ECHO_SERVICE_UUID, ECHO_CHARACTERISTIC_UUID)
+        .await
+        .unwrap();
+
+    let characteristic = Characteristic {
+        uuid: ECHO_CHARACTERISTIC_UUID,
+        service_uuid: ECHO_SERVICE_UUID,
+        ..Default::default()
+    };
+    peripheral
+        .write(
+            &characteristic,
+            b"hello echo",
+            CharacteristicWriteType::WriteWithResponse,
+        )
+        .await
+        .unwrap();
+
+    if let Some(value) = notifications.next().await {
+        log::info!("Echoed: {}", String::from_utf8_lossy(&value));
+    }
+}
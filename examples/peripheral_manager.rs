@@ -0,0 +1,40 @@
+use rustycore::Peripheral;
+use rustycore::api::{peripheral::PeripheralManager, peripheral_event::PeripheralEvent};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use log::LevelFilter;
+
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::formatted_builder()
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    setup_peripheral_manager().await;
+}
+
+async fn setup_peripheral_manager() {
+    let (sender_tx, mut receiver_rx) = mpsc::channel::<PeripheralEvent>(256);
+
+    let mut peripheral_manager = Peripheral::new(sender_tx).await.unwrap();
+    peripheral_manager
+        .wait_until_powered_on(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    // start advertising for centrals to connect
+    peripheral_manager
+        .start_advertising(Some("rustycore"), &[])
+        .await
+        .unwrap();
+    // Handle Updates
+    while let Some(event) = receiver_rx.recv().await {
+        handle_updates(event);
+    }
+}
+
+/// Listen to all updates and respond if require
+pub fn handle_updates(update: PeripheralEvent) {
+    log::info!("{:?}", update);
+}
@@ -0,0 +1,15 @@
+// Whether the app may use Bluetooth, as decided by the user or a device policy
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Authorization {
+    // The user hasn't been asked yet, creating any manager triggers the prompt
+    NotDetermined,
+    Restricted,
+    Denied,
+    Allowed,
+}
+
+impl Authorization {
+    pub fn is_allowed(&self) -> bool {
+        *self == Authorization::Allowed
+    }
+}
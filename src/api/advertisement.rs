@@ -0,0 +1,87 @@
+use uuid::Uuid;
+
+use crate::{Error, ErrorType, Result, api::uuids};
+
+// Bytes of a legacy advertising payload left once the 3 byte flags structure is in
+pub const MAX_ADVERTISEMENT_PAYLOAD: usize = 28;
+
+// Content of an advertisement sent by the peripheral role, see
+// PeripheralManager::advertise_beacon. CBPeripheralManager only advertises these two fields.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AdvertisementData {
+    pub local_name: Option<String>,
+    pub service_uuids: Vec<Uuid>,
+}
+
+impl AdvertisementData {
+    // Bytes the advertisement takes on air, every field is a structure with a length and a
+    // type byte. Service UUIDs are listed in the shortest form they have.
+    pub fn encoded_len(&self) -> usize {
+        let mut len = self.local_name.as_ref().map_or(0, |name| 2 + name.len());
+        for size in [2, 4, 16] {
+            let count = self
+                .service_uuids
+                .iter()
+                .filter(|uuid| uuid_len(**uuid) == size)
+                .count();
+            if count > 0 {
+                len += 2 + count * size;
+            }
+        }
+        len
+    }
+
+    // Fail with AdvertisementTooLarge when the advertisement doesn't fit a legacy payload
+    pub fn validate(&self) -> Result<()> {
+        let len = self.encoded_len();
+        if len > MAX_ADVERTISEMENT_PAYLOAD {
+            return Err(Error::from_string(
+                format!(
+                    "Advertisement takes {} bytes, at most {} fit",
+                    len, MAX_ADVERTISEMENT_PAYLOAD
+                ),
+                ErrorType::AdvertisementTooLarge,
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Bytes of the shortest form of `uuid`, SIG assigned UUIDs shrink to 16 or 32 bits
+fn uuid_len(uuid: Uuid) -> usize {
+    let value = uuid.as_u128();
+    let base = uuids::from_short(0).as_u128();
+    if value & !(0xFFFF << 96) == base {
+        2
+    } else if value & !(0xFFFF_FFFF << 96) == base {
+        4
+    } else {
+        16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_uuids_are_counted_in_their_short_form() {
+        let data = AdvertisementData {
+            local_name: Some("beacon".to_string()),
+            service_uuids: vec![uuids::from_short(0x180F), uuids::from_short(0x180A)],
+        };
+        // Name structure 2 + 6, one 16 bit UUID list 2 + 2 * 2
+        assert_eq!(data.encoded_len(), 14);
+        assert!(data.validate().is_ok());
+    }
+
+    #[test]
+    fn oversized_advertisement_is_rejected() {
+        let data = AdvertisementData {
+            local_name: Some("a long beacon name".to_string()),
+            service_uuids: vec![Uuid::from_u128(0x1234_5678_9abc_def0_1234_5678_9abc_def0)],
+        };
+        let error = data.validate().unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::AdvertisementTooLarge);
+    }
+}
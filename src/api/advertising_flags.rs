@@ -0,0 +1,87 @@
+use std::ops::BitOr;
+
+// AD type of the Flags field in raw advertising data
+pub const AD_TYPE_FLAGS: u8 = 0x01;
+
+// GAP advertising Flags (AD type 0x01, Core Specification Supplement part A, 1.3).
+// NOTE: CoreBluetooth strips the Flags field from the advertisement dictionary and never hands
+// out the raw advertising data, so on macOS this is only useful for raw data obtained some other
+// way, e.g. relayed by another device.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct AdvertisingFlags(u8);
+
+impl AdvertisingFlags {
+    pub const LE_LIMITED_DISCOVERABLE: Self = Self(0x01);
+    pub const LE_GENERAL_DISCOVERABLE: Self = Self(0x02);
+    pub const BR_EDR_NOT_SUPPORTED: Self = Self(0x04);
+    pub const LE_BR_EDR_CONTROLLER: Self = Self(0x08);
+    pub const LE_BR_EDR_HOST: Self = Self(0x10);
+
+    // Reserved bits are kept so the byte round trips
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_discoverable(&self) -> bool {
+        self.0 & (Self::LE_LIMITED_DISCOVERABLE.0 | Self::LE_GENERAL_DISCOVERABLE.0) != 0
+    }
+
+    // Find the Flags field in raw advertising data, a sequence of length, AD type, data
+    // structures. None when there is no Flags field or the data is malformed.
+    pub fn from_advertising_data(data: &[u8]) -> Option<Self> {
+        let mut rest = data;
+        while let [length, tail @ ..] = rest {
+            let length = *length as usize;
+            // A zero length ends the significant part of the data
+            if length == 0 || tail.len() < length {
+                return None;
+            }
+            let (structure, next) = tail.split_at(length);
+            if let [AD_TYPE_FLAGS, flags, ..] = structure {
+                return Some(Self(*flags));
+            }
+            rest = next;
+        }
+        None
+    }
+}
+
+impl BitOr for AdvertisingFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_flags_byte() {
+        // Flags structure followed by a complete 16 bit service UUID list
+        let data = [0x02, AD_TYPE_FLAGS, 0x06, 0x03, 0x03, 0x0F, 0x18];
+        let flags = AdvertisingFlags::from_advertising_data(&data).unwrap();
+        assert_eq!(
+            flags,
+            AdvertisingFlags::LE_GENERAL_DISCOVERABLE | AdvertisingFlags::BR_EDR_NOT_SUPPORTED
+        );
+        assert!(flags.is_discoverable());
+        assert!(!flags.contains(AdvertisingFlags::LE_LIMITED_DISCOVERABLE));
+    }
+
+    #[test]
+    fn missing_or_truncated_flags_yield_none() {
+        assert_eq!(AdvertisingFlags::from_advertising_data(&[0x03, 0x03, 0x0F, 0x18]), None);
+        assert_eq!(AdvertisingFlags::from_advertising_data(&[0x02, AD_TYPE_FLAGS]), None);
+    }
+}
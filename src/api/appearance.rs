@@ -0,0 +1,95 @@
+// GAP Appearance values (Assigned Numbers, section 2.6). Only the commonly used categories are
+// named, anything else is kept as its raw value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Appearance {
+    Unknown,
+    Phone,
+    Computer,
+    Watch,
+    Clock,
+    Display,
+    RemoteControl,
+    EyeGlasses,
+    Tag,
+    Keyring,
+    MediaPlayer,
+    BarcodeScanner,
+    Thermometer,
+    HeartRateSensor,
+    BloodPressure,
+    HumanInterfaceDevice,
+    Keyboard,
+    Mouse,
+    Joystick,
+    Gamepad,
+    GlucoseMeter,
+    RunningWalkingSensor,
+    CyclingSensor,
+    PulseOximeter,
+    WeightScale,
+    Other(u16),
+}
+
+impl From<u16> for Appearance {
+    fn from(value: u16) -> Self {
+        // Sub categories not named below fall back to their category (upper 10 bits)
+        match value {
+            961 => return Appearance::Keyboard,
+            962 => return Appearance::Mouse,
+            963 => return Appearance::Joystick,
+            964 => return Appearance::Gamepad,
+            _ => {}
+        }
+        match value >> 6 {
+            0 => Appearance::Unknown,
+            1 => Appearance::Phone,
+            2 => Appearance::Computer,
+            3 => Appearance::Watch,
+            4 => Appearance::Clock,
+            5 => Appearance::Display,
+            6 => Appearance::RemoteControl,
+            7 => Appearance::EyeGlasses,
+            8 => Appearance::Tag,
+            9 => Appearance::Keyring,
+            10 => Appearance::MediaPlayer,
+            11 => Appearance::BarcodeScanner,
+            12 => Appearance::Thermometer,
+            13 => Appearance::HeartRateSensor,
+            14 => Appearance::BloodPressure,
+            15 => Appearance::HumanInterfaceDevice,
+            16 => Appearance::GlucoseMeter,
+            17 => Appearance::RunningWalkingSensor,
+            18 => Appearance::CyclingSensor,
+            49 => Appearance::PulseOximeter,
+            50 => Appearance::WeightScale,
+            _ => Appearance::Other(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values_are_named() {
+        assert_eq!(Appearance::from(0), Appearance::Unknown);
+        assert_eq!(Appearance::from(64), Appearance::Phone);
+        assert_eq!(Appearance::from(832), Appearance::HeartRateSensor);
+        assert_eq!(Appearance::from(962), Appearance::Mouse);
+        assert_eq!(Appearance::from(3200), Appearance::WeightScale);
+    }
+
+    #[test]
+    fn sub_category_falls_back_to_its_category() {
+        // Heart rate belt (0x0341) is a heart rate sensor, a generic HID (0x03C0) stays HID
+        assert_eq!(Appearance::from(833), Appearance::HeartRateSensor);
+        assert_eq!(Appearance::from(960), Appearance::HumanInterfaceDevice);
+    }
+
+    #[test]
+    fn unknown_values_keep_their_raw_value() {
+        assert_eq!(Appearance::from(1216), Appearance::Other(1216));
+        assert_eq!(Appearance::from(u16::MAX), Appearance::Other(u16::MAX));
+    }
+}
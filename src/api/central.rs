@@ -1,17 +1,28 @@
+use crate::api::appearance::Appearance;
 use crate::api::central_event::CentralEvent;
 use crate::api::central_event::CentralState;
 use crate::api::characteristic::Characteristic;
 use crate::api::characteristic::CharacteristicProperty;
 use crate::api::characteristic::CharacteristicWriteType;
+use crate::api::config::Config;
+use crate::api::connection_parameters::ConnectionParameters;
 use crate::api::descriptor::Descriptor;
+use crate::api::device_info::DeviceInformation;
+use crate::api::presentation_format::PresentationFormat;
 use crate::api::service::Service;
-use std::collections::BTreeSet;
-use std::fmt::Debug;
+use crate::api::uuids;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::{Debug, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender;
 
-use crate::Result;
+use crate::{Error, ErrorType, Result, util};
 
 use async_trait::async_trait;
+use futures::FutureExt;
+use futures::future::{self, BoxFuture};
+use futures::stream::{BoxStream, StreamExt};
 use uuid::Uuid;
 
 #[async_trait]
@@ -21,6 +32,11 @@ pub trait CentralManager: Send + Sync {
 
     async fn new(sender_tx: Sender<CentralEvent>) -> Result<Self::CentralManager>;
 
+    async fn new_with_config(
+        sender_tx: Sender<CentralEvent>,
+        config: Config,
+    ) -> Result<Self::CentralManager>;
+
     async fn start_scan(&mut self, filter: ScanFilter) -> Result<bool>;
 
     async fn stop_scan(&mut self) -> Result<()>;
@@ -32,11 +48,206 @@ pub trait CentralManager: Send + Sync {
     async fn adapter_info(&mut self) -> Result<String>;
 
     async fn adapter_state(&mut self) -> Result<CentralState>;
+
+    // Wait for the adapter to power on, a new manager starts out in Unknown and commands issued
+    // before PoweredOn fail. Errors on Unsupported, Unauthorized or after `timeout`.
+    async fn wait_until_powered_on(&mut self, timeout: Duration) -> Result<()>;
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct ScanFilter {
     pub services: Vec<Uuid>,
+    // NOTE: CoreBluetooth only filters on the regular advertised services, a device listing a
+    // service solely in the Apple overflow area is dropped by the OS. With this set the scan is
+    // unfiltered at the OS level and `services` is matched here against both lists instead.
+    pub match_overflow: bool,
+    // NOTE: Best effort only, macOS assigns peripheral IDs locally so they are only known once a
+    // device has been discovered on this machine and may change after the system forgets it.
+    // Pair it with `services` so the scan itself stays narrow, the whitelist is only applied to
+    // discoveries afterwards. Empty allows every device.
+    pub id_whitelist: Vec<PeripheralId>,
+    // Only report an advertisement whose content differs from the last one of the same device,
+    // a change in RSSI alone doesn't count. Applies to the discovery, manufacturer data, service
+    // data and services events separately.
+    pub emit_on_change_only: bool,
+    // Cap on the advertisements reported per device and second, a busy beacon beyond it has
+    // the excess dropped whole (discovery and advertisement data events alike). Unlike
+    // disabling duplicates this only quiets the noisy devices. None doesn't limit.
+    pub max_events_per_device_per_sec: Option<u32>,
+}
+
+impl ScanFilter {
+    // Filter on well-known services by name, e.g. &["Heart Rate", "Battery"]. Fails on the
+    // first name uuids::service_by_name doesn't know.
+    pub fn for_named(names: &[&str]) -> Result<Self> {
+        let services = names
+            .iter()
+            .map(|name| {
+                uuids::service_by_name(name).ok_or_else(|| {
+                    Error::from_string(format!("Unknown service name {:?}", name), ErrorType::NotFound)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ScanFilter {
+            services,
+            ..Default::default()
+        })
+    }
+
+    // Whether device `id` advertising `advertised` services (regular and overflow) passes
+    pub fn matches(&self, id: &PeripheralId, advertised: &[Uuid]) -> bool {
+        let service_match = self.services.is_empty()
+            || advertised.iter().any(|uuid| self.services.contains(uuid));
+        let id_match = self.id_whitelist.is_empty() || self.id_whitelist.contains(id);
+        service_match && id_match
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiscoveredDevice {
+    pub id: PeripheralId,
+    pub name: String,
+    pub rssi: i16,
+}
+
+// Ranks devices by the average of their last few RSSI samples so a single strong or weak
+// advertisement doesn't reorder them
+#[derive(Clone, Debug)]
+pub struct RssiRanking {
+    samples_per_device: usize,
+    devices: HashMap<PeripheralId, (DiscoveredDevice, VecDeque<i16>)>,
+}
+
+impl RssiRanking {
+    pub fn new(samples_per_device: usize) -> Self {
+        RssiRanking {
+            samples_per_device: samples_per_device.max(1),
+            devices: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, device: DiscoveredDevice) {
+        let (latest, samples) = self
+            .devices
+            .entry(device.id.clone())
+            .or_insert_with(|| (device.clone(), VecDeque::new()));
+        if samples.len() == self.samples_per_device {
+            samples.pop_front();
+        }
+        samples.push_back(device.rssi);
+        *latest = device;
+    }
+
+    // Smoothed RSSI of a device, None if it was never recorded
+    pub fn average_rssi(&self, id: &PeripheralId) -> Option<f64> {
+        let (_, samples) = self.devices.get(id)?;
+        let sum: i64 = samples.iter().map(|rssi| *rssi as i64).sum();
+        Some(sum as f64 / samples.len() as f64)
+    }
+
+    // Latest advertisement of every device, strongest smoothed RSSI first
+    pub fn ranked(&self) -> Vec<DiscoveredDevice> {
+        let mut ranked: Vec<(f64, &DiscoveredDevice)> = self
+            .devices
+            .iter()
+            .filter_map(|(id, (device, _))| Some((self.average_rssi(id)?, device)))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranked.into_iter().map(|(_, device)| device.clone()).collect()
+    }
+}
+
+impl Default for RssiRanking {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+// Steps of Central::provision in the order they run
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProvisionStep {
+    Scan,
+    Connect,
+    Discover,
+    Write,
+    Verify,
+    Disconnect,
+}
+
+// Failure of a provisioning run along with the step it happened in
+#[derive(Clone, Debug)]
+pub struct ProvisionError {
+    pub step: ProvisionStep,
+    pub error: Error,
+}
+
+impl std::fmt::Display for ProvisionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Provisioning failed at {:?}: {}", self.step, self.error)
+    }
+}
+
+impl std::error::Error for ProvisionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Provisioned {
+    pub device: DiscoveredDevice,
+    // Value of the verify characteristic read back after writing the credentials
+    pub verification: Vec<u8>,
+}
+
+// Requested trade off between latency and power use for a connection
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ConnectionPriority {
+    #[default]
+    Balanced,
+    High,
+    LowPower,
+}
+
+// LE physical layer of a connection
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phy {
+    Le1M,
+    Le2M,
+    LeCoded,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectOptions {
+    // Read every readable characteristic once a service discovery completes, one read at a time,
+    // before reporting the services
+    pub read_all_on_discover: bool,
+}
+
+// What a notification stream does with new values while its consumer is lagging behind
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BackpressureStrategy {
+    // Wait for room, which also holds up every other event of the peripheral
+    #[default]
+    Block,
+    // Drop the oldest buffered value to make room
+    DropOldest,
+    // Drop the value that doesn't fit
+    DropNewest,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SubscribeOptions {
+    pub backpressure: BackpressureStrategy,
+}
+
+// Names a peripheral is known by outside of its GATT database
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PeripheralNames {
+    // CBPeripheral.name, cached by CoreBluetooth from the GAP name or a previous advertisement
+    pub cached: Option<String>,
+    // Local name of the latest advertisement
+    pub advertised: Option<String>,
 }
 
 #[async_trait]
@@ -49,22 +260,142 @@ pub trait PeripheralRemote: Send + Sync {
 
     async fn properties(&self) -> Result<Option<CharacteristicProperty>>;
 
+    async fn names(&self) -> Result<PeripheralNames>;
+
+    // The most reliable label for the peripheral: the GAP Device Name when connected, then the
+    // name cached by CoreBluetooth and last the advertised local name
+    async fn best_name(&self) -> Option<String> {
+        if self.is_connected().await.unwrap_or(false) {
+            let device_name = self
+                .characteristics()
+                .into_iter()
+                .find(|characteristic| characteristic.uuid == uuids::DEVICE_NAME);
+            if let Some(characteristic) = device_name
+                && let Ok(value) = self.read(&characteristic).await
+            {
+                let name = String::from_utf8_lossy(&value)
+                    .trim_end_matches('\0')
+                    .to_string();
+                if !name.is_empty() {
+                    return Some(name);
+                }
+            }
+        }
+        let names = self.names().await.ok()?;
+        names.cached.or(names.advertised)
+    }
+
     fn services(&self) -> BTreeSet<Service>;
 
+    // Indented tree of the discovered services, characteristics and descriptors for diagnostics
+    fn dump_gatt(&self) -> String {
+        let label = |uuid: Uuid| match uuids::short_name(uuid) {
+            Some(name) => format!("{} ({})", uuid, name),
+            None => uuid.to_string(),
+        };
+        let mut dump = String::new();
+        for service in self.services() {
+            let kind = if service.primary { "primary" } else { "secondary" };
+            let _ = writeln!(dump, "Service {} [{}]", label(service.uuid), kind);
+            for characteristic in service.characteristics.iter() {
+                let _ = writeln!(
+                    dump,
+                    "  Characteristic {} {:?}",
+                    label(characteristic.uuid),
+                    characteristic.properties
+                );
+                for descriptor in characteristic.descriptors.iter() {
+                    let _ = writeln!(dump, "    Descriptor {}", label(descriptor.uuid));
+                }
+            }
+        }
+        dump
+    }
+
     fn characteristics(&self) -> BTreeSet<Characteristic> {
         self.services()
             .iter()
             .flat_map(|service| service.characteristics.clone().into_iter())
             .collect()
     }
+
+    // Characteristics of a single service in the order the peripheral declares them
+    fn characteristics_of(&self, service_uuid: Uuid) -> Vec<Characteristic> {
+        self.services()
+            .into_iter()
+            .find(|service| service.uuid == service_uuid)
+            .map(|service| service.characteristics)
+            .unwrap_or_default()
+    }
+
+    // Whether the last discovery found service `uuid`
+    fn has_service(&self, uuid: Uuid) -> bool {
+        self.services().iter().any(|service| service.uuid == uuid)
+    }
+
+    // Whether the last discovery found `characteristic` within `service`
+    fn has_characteristic(&self, service: Uuid, characteristic: Uuid) -> bool {
+        self.services()
+            .iter()
+            .filter(|cached| cached.uuid == service)
+            .flat_map(|cached| cached.characteristics.iter())
+            .any(|cached| cached.uuid == characteristic)
+    }
+
     async fn is_connected(&self) -> Result<bool>;
 
-    async fn connect(&self) -> Result<()>;
+    async fn connect(&self) -> Result<()> {
+        self.connect_with_options(ConnectOptions::default()).await
+    }
+
+    async fn connect_with_options(&self, options: ConnectOptions) -> Result<()>;
 
     async fn disconnect(&self) -> Result<()>;
 
+    // Connect, run `f` and disconnect again, also when `f` errors or panics. The error of `f`
+    // takes precedence over a failed disconnect.
+    async fn with_connection<F, T>(&self, f: F) -> Result<T>
+    where
+        Self: Sized,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<T>> + Send,
+        T: Send,
+    {
+        self.connect().await?;
+        let outcome = AssertUnwindSafe(f(self)).catch_unwind().await;
+        let disconnected = self.disconnect().await;
+        match outcome {
+            Ok(result) => {
+                let value = result?;
+                disconnected?;
+                Ok(value)
+            }
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+
     async fn discover_services(&self) -> Result<()>;
 
+    // Stop waiting on a discovery in progress, the pending discover_services or refresh fails
+    // with a Cancelled error
+    async fn cancel_discovery(&self) -> Result<()>;
+
+    // Fail every pending read, write, (un)subscribe, descriptor operation and discovery with a
+    // Cancelled error while staying connected. Late answers from the peripheral are ignored.
+    async fn cancel_all_operations(&self) -> Result<()>;
+
+    // Drop everything cached about the GATT tree and discover it again, resolves with the fresh
+    // services once discovery has fully completed. Meant to be called after ServicesModified.
+    async fn refresh(&self) -> Result<Vec<Service>>;
+
+    // Payload budget for a single write of the given type, write without response is limited
+    // to the negotiated MTU minus the ATT header while write with response can use a long write.
+    fn recommended_chunk_size(&self, write_type: CharacteristicWriteType) -> usize;
+
+    // Write types `characteristic` declares support for, in order of preference
+    fn supported_write_types(&self, characteristic: &Characteristic) -> Vec<CharacteristicWriteType> {
+        write_types_of(characteristic)
+    }
+
     async fn write(
         &self,
         characteristic: &Characteristic,
@@ -72,18 +403,466 @@ pub trait PeripheralRemote: Send + Sync {
         write_type: CharacteristicWriteType,
     ) -> Result<()>;
 
+    // Same as write but returns the number of bytes actually written
+    async fn write_counted(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: CharacteristicWriteType,
+    ) -> Result<usize>;
+
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>>;
 
+    // Same as read along with when the value arrived, for polling loops logging a time series
+    async fn read_timestamped(&self, characteristic: &Characteristic) -> Result<(Vec<u8>, Instant)> {
+        let value = self.read(characteristic).await?;
+        Ok((value, Instant::now()))
+    }
+
+    // Same as read but overrides the default operation timeout for this call only, useful for
+    // slow sensors that legitimately take longer to answer.
+    async fn read_with_timeout(
+        &self,
+        characteristic: &Characteristic,
+        timeout: Duration,
+    ) -> Result<Vec<u8>>;
+
     // subscribe to notifications
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()>;
 
+    // Subscribe to all `characteristics` at once, every setNotifyValue is issued before any
+    // confirmation is awaited. Results are in the order of `characteristics`, one failing
+    // doesn't affect the others.
+    async fn subscribe_many(&self, characteristics: &[Characteristic]) -> Result<Vec<Result<()>>> {
+        let subscribes = characteristics
+            .iter()
+            .map(|characteristic| self.subscribe(characteristic));
+        Ok(future::join_all(subscribes).await)
+    }
+
+    // Connect if needed, discover services and subscribe to `characteristic`, returning a stream
+    // of its notifications. Dropping the stream unsubscribes.
+    async fn connect_subscribe(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+    ) -> Result<BoxStream<'static, Vec<u8>>> {
+        self.connect_subscribe_with_options(service, characteristic, SubscribeOptions::default())
+            .await
+    }
+
+    async fn connect_subscribe_with_options(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        options: SubscribeOptions,
+    ) -> Result<BoxStream<'static, Vec<u8>>>;
+
+    // Same as subscribe but notifications repeating the previous value are not forwarded
+    async fn subscribe_on_change(&self, characteristic: &Characteristic) -> Result<()>;
+
+    // NOTE: CoreBluetooth gives the central no control over the connection interval or latency,
+    // the peripheral decides. The preference is only recorded so apps can carry it across
+    // reconnections and act on it, e.g. by lowering their own polling rate.
+    fn set_connection_priority(&self, priority: ConnectionPriority);
+
+    fn connection_priority(&self) -> ConnectionPriority;
+
+    // Error of the last read, write or subscribe of `characteristic` that failed, cleared by the
+    // next one that succeeds. Shared by every handle of the peripheral.
+    fn last_error(&self, characteristic: &Characteristic) -> Option<Error>;
+
+    // PHY the connection currently runs on, None where the platform doesn't report it.
+    // NOTE: CoreBluetooth negotiates the PHY on its own and exposes neither the current PHY nor a
+    // way to request one, so this is always None on macOS.
+    fn current_phy(&self) -> Option<Phy> {
+        None
+    }
+
     // unsubscribe to notifications
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()>;
 
+    // NOTE: CoreBluetooth doesn't include the appearance in the advertisement data and usually
+    // hides the Generic Access service, so this only works for peripherals exposing 0x2A01.
+    async fn read_appearance(&self) -> Result<Appearance> {
+        let characteristic = self
+            .characteristics()
+            .into_iter()
+            .find(|characteristic| characteristic.uuid == uuids::APPEARANCE)
+            .ok_or_else(|| {
+                Error::from_string(
+                    "Appearance characteristic not found".to_string(),
+                    ErrorType::NotFound,
+                )
+            })?;
+        let value = self.read(&characteristic).await?;
+        match util::read_array(&value, 0) {
+            Some(bytes) => Ok(Appearance::from(u16::from_le_bytes(bytes))),
+            None => Err(Error::from_string(
+                format!("Appearance value too short: {} bytes", value.len()),
+                ErrorType::InvalidValue,
+            )),
+        }
+    }
+
+    // Same caveat as read_appearance, the Generic Access service is usually hidden by CoreBluetooth
+    async fn preferred_connection_parameters(&self) -> Result<ConnectionParameters> {
+        let characteristic = self
+            .characteristics()
+            .into_iter()
+            .find(|characteristic| {
+                characteristic.uuid == uuids::PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS
+            })
+            .ok_or_else(|| {
+                Error::from_string(
+                    "Preferred connection parameters characteristic not found".to_string(),
+                    ErrorType::NotFound,
+                )
+            })?;
+        let value = self.read(&characteristic).await?;
+        ConnectionParameters::from_bytes(&value)
+    }
+
+    // Read `characteristic` rendered through its Presentation Format descriptor, e.g. "36.5 °C".
+    // Falls back to hex when there is no such descriptor or its format isn't supported.
+    async fn read_formatted(&self, characteristic: &Characteristic) -> Result<String> {
+        let value = self.read(characteristic).await?;
+        let descriptor = characteristic
+            .descriptors
+            .iter()
+            .find(|descriptor| descriptor.uuid == uuids::CHARACTERISTIC_PRESENTATION_FORMAT);
+        let formatted = match descriptor {
+            Some(descriptor) => {
+                let format = PresentationFormat::from_bytes(&self.read_descriptor(descriptor).await?)?;
+                format.format_value(&value)
+            }
+            None => None,
+        };
+        Ok(formatted.unwrap_or_else(|| {
+            value.iter().fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            })
+        }))
+    }
+
+    // Read every Device Information Service string the peripheral exposes, discovering services
+    // first when the service isn't known yet
+    async fn device_info(&self) -> Result<DeviceInformation> {
+        let mut characteristics = self.characteristics_of(uuids::DEVICE_INFORMATION);
+        if characteristics.is_empty() {
+            self.discover_services().await?;
+            characteristics = self.characteristics_of(uuids::DEVICE_INFORMATION);
+        }
+        let mut info = DeviceInformation::default();
+        for characteristic in characteristics.iter() {
+            let field = match characteristic.uuid {
+                uuids::MANUFACTURER_NAME_STRING => &mut info.manufacturer_name,
+                uuids::MODEL_NUMBER_STRING => &mut info.model_number,
+                uuids::SERIAL_NUMBER_STRING => &mut info.serial_number,
+                uuids::HARDWARE_REVISION_STRING => &mut info.hardware_revision,
+                uuids::FIRMWARE_REVISION_STRING => &mut info.firmware_revision,
+                uuids::SOFTWARE_REVISION_STRING => &mut info.software_revision,
+                _ => continue,
+            };
+            let value = self.read(characteristic).await?;
+            // Some devices pad their strings with trailing NULs
+            *field = Some(
+                String::from_utf8_lossy(&value)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            );
+        }
+        Ok(info)
+    }
+
+    // Battery Level in percent, discovering services first when the Battery service isn't known yet
+    async fn battery_level(&self) -> Result<u8> {
+        if self.characteristics_of(uuids::BATTERY).is_empty() {
+            self.discover_services().await?;
+        }
+        let characteristic = self
+            .characteristics_of(uuids::BATTERY)
+            .into_iter()
+            .find(|characteristic| characteristic.uuid == uuids::BATTERY_LEVEL)
+            .ok_or_else(|| {
+                Error::from_string(
+                    "Battery Level characteristic not found".to_string(),
+                    ErrorType::NotFound,
+                )
+            })?;
+        battery_percent(&self.read(&characteristic).await?)
+    }
+
+    // Battery Level notifications in percent, empty or out of range notifications are skipped
+    async fn battery_stream(&self) -> Result<BoxStream<'static, u8>> {
+        let notifications = self
+            .connect_subscribe(uuids::BATTERY, uuids::BATTERY_LEVEL)
+            .await?;
+        Ok(notifications
+            .filter_map(|value| future::ready(battery_percent(&value).ok()))
+            .boxed())
+    }
+
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()>;
 
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>>;
+
+    // Whether the Server Characteristic Configuration (0x2903) has broadcasting enabled
+    async fn read_broadcast_configuration(&self, characteristic: &Characteristic) -> Result<bool> {
+        let descriptor = server_configuration_descriptor(characteristic)?;
+        let value = self.read_descriptor(descriptor).await?;
+        match value.first() {
+            Some(flags) => Ok(flags & SERVER_CONFIGURATION_BROADCAST != 0),
+            None => Err(Error::from_string(
+                "Server Characteristic Configuration value is empty".to_string(),
+                ErrorType::InvalidValue,
+            )),
+        }
+    }
+
+    async fn write_broadcast_configuration(
+        &self,
+        characteristic: &Characteristic,
+        enabled: bool,
+    ) -> Result<()> {
+        let descriptor = server_configuration_descriptor(characteristic)?;
+        let flags = if enabled {
+            SERVER_CONFIGURATION_BROADCAST
+        } else {
+            0
+        };
+        self.write_descriptor(descriptor, &[flags, 0]).await
+    }
+
+    // Ask the peripheral to include the characteristic value in its advertisements
+    async fn enable_broadcast(&self, characteristic: &Characteristic) -> Result<()> {
+        self.write_broadcast_configuration(characteristic, true)
+            .await
+    }
+}
+
+// Bit 0 of the Server Characteristic Configuration value
+const SERVER_CONFIGURATION_BROADCAST: u8 = 0x01;
+
+fn server_configuration_descriptor(characteristic: &Characteristic) -> Result<&Descriptor> {
+    if !characteristic
+        .properties
+        .contains(&CharacteristicProperty::Broadcast)
+    {
+        return Err(Error::from_string(
+            format!("Characteristic {} does not support broadcast", characteristic.uuid),
+            ErrorType::NotSupported,
+        ));
+    }
+    characteristic
+        .descriptors
+        .iter()
+        .find(|descriptor| descriptor.uuid == uuids::SERVER_CHARACTERISTIC_CONFIGURATION)
+        .ok_or_else(|| {
+            Error::from_string(
+                format!(
+                    "Characteristic {} has no Server Characteristic Configuration descriptor",
+                    characteristic.uuid
+                ),
+                ErrorType::NotFound,
+            )
+        })
+}
+
+fn write_types_of(characteristic: &Characteristic) -> Vec<CharacteristicWriteType> {
+    let mut write_types = Vec::new();
+    if characteristic.properties.contains(&CharacteristicProperty::Write) {
+        write_types.push(CharacteristicWriteType::WriteWithResponse);
+    }
+    if characteristic
+        .properties
+        .contains(&CharacteristicProperty::WriteWithoutResponse)
+    {
+        write_types.push(CharacteristicWriteType::WriteWithoutResponse);
+    }
+    if characteristic
+        .properties
+        .contains(&CharacteristicProperty::AuthenticatedSignedWrites)
+    {
+        write_types.push(CharacteristicWriteType::SignedWriteWithoutResponse);
+    }
+    write_types
+}
+
+// Battery Level is a single byte from 0 to 100 percent
+fn battery_percent(value: &[u8]) -> Result<u8> {
+    match value.first() {
+        Some(percent) if *percent <= 100 => Ok(*percent),
+        Some(percent) => Err(Error::from_string(
+            format!("Battery Level {} is above 100 percent", percent),
+            ErrorType::InvalidValue,
+        )),
+        None => Err(Error::from_string(
+            "Empty Battery Level value".to_string(),
+            ErrorType::InvalidValue,
+        )),
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct PeripheralId(Uuid);
+
+impl PeripheralId {
+    pub fn uuid(&self) -> Uuid {
+        self.0
+    }
+}
+
+impl From<Uuid> for PeripheralId {
+    fn from(uuid: Uuid) -> Self {
+        PeripheralId(uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(n: u128) -> PeripheralId {
+        Uuid::from_u128(n).into()
+    }
+
+    // With match_overflow the advertised list holds the regular and overflow services alike
+    #[test]
+    fn overflow_service_matches() {
+        let filter = ScanFilter {
+            services: vec![uuids::HEART_RATE],
+            match_overflow: true,
+            ..Default::default()
+        };
+        let advertised = [uuids::BATTERY, uuids::HEART_RATE];
+        assert!(filter.matches(&device(1), &advertised));
+    }
+
+    #[test]
+    fn overflow_without_the_service_does_not_match() {
+        let filter = ScanFilter {
+            services: vec![uuids::HEART_RATE],
+            match_overflow: true,
+            ..Default::default()
+        };
+        assert!(!filter.matches(&device(1), &[uuids::BATTERY]));
+        assert!(!filter.matches(&device(1), &[]));
+    }
+
+    #[test]
+    fn whitelisted_device_is_allowed() {
+        let filter = ScanFilter {
+            id_whitelist: vec![device(1)],
+            ..Default::default()
+        };
+        assert!(filter.matches(&device(1), &[]));
+    }
+
+    #[test]
+    fn device_off_the_whitelist_is_rejected() {
+        let filter = ScanFilter {
+            services: vec![uuids::HEART_RATE],
+            id_whitelist: vec![device(1)],
+            ..Default::default()
+        };
+        assert!(!filter.matches(&device(2), &[uuids::HEART_RATE]));
+        // Both the services and the whitelist have to match
+        assert!(!filter.matches(&device(1), &[uuids::BATTERY]));
+    }
+
+    #[test]
+    fn named_services_are_looked_up_ignoring_case() {
+        let filter = ScanFilter::for_named(&["Heart Rate", "battery"]).unwrap();
+        assert_eq!(filter.services, vec![uuids::HEART_RATE, uuids::BATTERY]);
+        assert!(!filter.match_overflow);
+    }
+
+    #[test]
+    fn unknown_service_name_fails() {
+        let error = ScanFilter::for_named(&["Heart Rate", "Toaster"]).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::NotFound);
+    }
+
+    fn discovered(n: u128, rssi: i16) -> DiscoveredDevice {
+        DiscoveredDevice {
+            id: device(n),
+            name: format!("device {}", n),
+            rssi,
+        }
+    }
+
+    // One strong advertisement doesn't lift a device above a steadily stronger one
+    #[test]
+    fn ranking_orders_by_the_average_rssi() {
+        let mut ranking = RssiRanking::new(3);
+        for rssi in [-60, -62, -61] {
+            ranking.record(discovered(1, rssi));
+        }
+        for rssi in [-80, -80, -40] {
+            ranking.record(discovered(2, rssi));
+        }
+        ranking.record(discovered(3, -90));
+        let order: Vec<_> = ranking.ranked().into_iter().map(|device| device.id).collect();
+        assert_eq!(order, vec![device(1), device(2), device(3)]);
+        assert_eq!(ranking.average_rssi(&device(1)), Some(-61.0));
+    }
+
+    #[test]
+    fn ranking_keeps_only_the_last_samples() {
+        let mut ranking = RssiRanking::new(2);
+        for rssi in [-30, -70, -70] {
+            ranking.record(discovered(1, rssi));
+        }
+        ranking.record(discovered(2, -60));
+        assert_eq!(ranking.average_rssi(&device(1)), Some(-70.0));
+        assert_eq!(ranking.ranked()[0].id, device(2));
+        assert_eq!(ranking.average_rssi(&device(3)), None);
+    }
+
+    #[test]
+    fn battery_level_in_range_is_read() {
+        assert_eq!(battery_percent(&[0]).unwrap(), 0);
+        assert_eq!(battery_percent(&[100]).unwrap(), 100);
+        // Bytes past the first are ignored
+        assert_eq!(battery_percent(&[42, 7]).unwrap(), 42);
+    }
+
+    #[test]
+    fn battery_level_out_of_range_or_empty_is_rejected() {
+        let error = battery_percent(&[101]).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InvalidValue);
+        let error = battery_percent(&[]).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InvalidValue);
+    }
+
+    #[test]
+    fn write_types_follow_the_properties_in_order_of_preference() {
+        let characteristic = Characteristic {
+            properties: vec![
+                CharacteristicProperty::AuthenticatedSignedWrites,
+                CharacteristicProperty::WriteWithoutResponse,
+                CharacteristicProperty::Write,
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            write_types_of(&characteristic),
+            vec![
+                CharacteristicWriteType::WriteWithResponse,
+                CharacteristicWriteType::WriteWithoutResponse,
+                CharacteristicWriteType::SignedWriteWithoutResponse,
+            ]
+        );
+    }
+
+    #[test]
+    fn read_only_characteristic_has_no_write_types() {
+        let characteristic = Characteristic {
+            properties: vec![CharacteristicProperty::Read, CharacteristicProperty::Notify],
+            ..Default::default()
+        };
+        assert!(write_types_of(&characteristic).is_empty());
+    }
+}
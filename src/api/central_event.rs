@@ -1,6 +1,29 @@
 use std::collections::HashMap;
+use std::time::Duration;
 use uuid::Uuid;
 
+use crate::api::descriptor::Descriptor;
+use crate::api::service::Service;
+use crate::api::uuids;
+
+// Value carried by notifications, a Vec<u8> by default or a zero-copy view over the NSData
+// received from CoreBluetooth when the `bytes` feature is enabled
+#[cfg(not(feature = "bytes"))]
+pub type Payload = Vec<u8>;
+#[cfg(feature = "bytes")]
+pub type Payload = bytes::Bytes;
+
+// Owned bytes of a payload for reads and notification streams, a no-op without `bytes`
+#[cfg(not(feature = "bytes"))]
+pub fn payload_into_vec(value: Payload) -> Vec<u8> {
+    value
+}
+
+#[cfg(feature = "bytes")]
+pub fn payload_into_vec(value: Payload) -> Vec<u8> {
+    Vec::from(value)
+}
+
 #[derive(Debug, Clone)]
 pub enum CentralEvent {
     DeviceDiscovered {
@@ -11,11 +34,21 @@ pub enum CentralEvent {
     DeviceUpdated {
         server: Uuid,
     },
+    // The running scan was stopped, see Central::stop_scan
+    ScanStopped,
+    // A device seen while monitoring presence stopped advertising for longer than the absence
+    // timeout (see Central::monitor_presence)
+    DeviceLost {
+        server: Uuid,
+    },
     DeviceConnected {
         server: Uuid,
+        // The peripheral was already connected earlier this session
+        reconnect: bool,
     },
     DeviceDisconnected {
         server: Uuid,
+        reason: DisconnectReason,
     },
     DeviceConnectionFailed {
         server: Uuid,
@@ -28,7 +61,7 @@ pub enum CentralEvent {
     },
     ServiceDataAdvertisement {
         server: Uuid,
-        service_data: HashMap<Uuid, Vec<u8>>,
+        service_data: ServiceData,
     },
     ServicesAdvertisement {
         server: Uuid,
@@ -37,7 +70,239 @@ pub enum CentralEvent {
     StateUpdate {
         state: CentralState,
     },
+    // Descriptors of a characteristic were discovered, reported as they arrive during a discovery
+    DescriptorsDiscovered {
+        server: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        descriptors: Vec<Descriptor>,
+    },
+    // A service discovery completed, characteristic values are filled in when connected with
+    // ConnectOptions::read_all_on_discover
+    ServicesDiscovered {
+        server: Uuid,
+        services: Vec<Service>,
+    },
+    // The peripheral changed its GATT database, the listed services are no longer valid and
+    // need to be rediscovered (see PeripheralRemote::refresh)
+    ServicesModified {
+        server: Uuid,
+        invalidated_services: Vec<Uuid>,
+    },
+    // The peripheral exposes more than one instance of service `uuid`, only the first instance is
+    // discovered and part of the service tree
+    DuplicateService {
+        server: Uuid,
+        uuid: Uuid,
+    },
+    CharacteristicNotified {
+        server: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        value: Payload,
+        // Position of this notification within its subscription, starting at 0. Values filtered
+        // by subscribe_on_change don't take a number, so a gap means events were dropped.
+        sequence: u64,
+        // Arrival order across every characteristic and peripheral of the process
+        global_sequence: u64,
+    },
+    // Round trip of a successful write with response, from handing it to CoreBluetooth until
+    // the peripheral confirmed it. A growing latency hints at a degrading link.
+    #[cfg(feature = "metrics")]
+    CharacteristicWriteLatency {
+        server: Uuid,
+        characteristic_uuid: Uuid,
+        latency: Duration,
+    },
+}
 
+// Service data of an advertisement keyed by the full service UUID
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ServiceData(HashMap<Uuid, Vec<u8>>);
+
+impl ServiceData {
+    pub fn get(&self, uuid: &Uuid) -> Option<&[u8]> {
+        self.0.get(uuid).map(Vec::as_slice)
+    }
+
+    // Lookup by a 16 bit SIG assigned UUID, e.g. 0xFEAA for Eddystone
+    pub fn get_u16(&self, short_uuid: u16) -> Option<&[u8]> {
+        self.get(&uuids::from_short(short_uuid))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Uuid, &Vec<u8>)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<HashMap<Uuid, Vec<u8>>> for ServiceData {
+    fn from(service_data: HashMap<Uuid, Vec<u8>>) -> Self {
+        ServiceData(service_data)
+    }
+}
+
+// Flat view of a CentralEvent for structured logging and metrics. Borrows from the event, only
+// the fields relevant to the event kind are set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventRecord<'a> {
+    pub kind: &'static str,
+    pub peripheral_id: Option<Uuid>,
+    pub name: Option<&'a str>,
+    pub rssi: Option<i16>,
+    pub service_uuid: Option<Uuid>,
+    pub characteristic_uuid: Option<Uuid>,
+    pub manufacturer_id: Option<u16>,
+    pub value_len: Option<usize>,
+    pub reconnect: Option<bool>,
+    pub reason: Option<&'a DisconnectReason>,
+    pub state: Option<&'a CentralState>,
+    pub error: Option<&'a str>,
+    pub latency: Option<Duration>,
+}
+
+impl CentralEvent {
+    pub fn to_record(&self) -> EventRecord<'_> {
+        match self {
+            CentralEvent::DeviceDiscovered { server, name, rssi } => EventRecord {
+                kind: "device_discovered",
+                peripheral_id: Some(*server),
+                name: Some(name),
+                rssi: Some(*rssi),
+                ..Default::default()
+            },
+            CentralEvent::DeviceUpdated { server } => EventRecord {
+                kind: "device_updated",
+                peripheral_id: Some(*server),
+                ..Default::default()
+            },
+            CentralEvent::ScanStopped => EventRecord {
+                kind: "scan_stopped",
+                ..Default::default()
+            },
+            CentralEvent::DeviceLost { server } => EventRecord {
+                kind: "device_lost",
+                peripheral_id: Some(*server),
+                ..Default::default()
+            },
+            CentralEvent::DeviceConnected { server, reconnect } => EventRecord {
+                kind: "device_connected",
+                peripheral_id: Some(*server),
+                reconnect: Some(*reconnect),
+                ..Default::default()
+            },
+            CentralEvent::DeviceDisconnected { server, reason } => EventRecord {
+                kind: "device_disconnected",
+                peripheral_id: Some(*server),
+                reason: Some(reason),
+                ..Default::default()
+            },
+            CentralEvent::DeviceConnectionFailed { server, error } => EventRecord {
+                kind: "device_connection_failed",
+                peripheral_id: Some(*server),
+                error: error.as_deref(),
+                ..Default::default()
+            },
+            CentralEvent::ManufacturerDataAdvertisement {
+                server,
+                manufacturer_id,
+                manufacturer_data,
+            } => EventRecord {
+                kind: "manufacturer_data_advertisement",
+                peripheral_id: Some(*server),
+                manufacturer_id: Some(*manufacturer_id),
+                value_len: Some(manufacturer_data.len()),
+                ..Default::default()
+            },
+            CentralEvent::ServiceDataAdvertisement {
+                server,
+                service_data,
+            } => EventRecord {
+                kind: "service_data_advertisement",
+                peripheral_id: Some(*server),
+                value_len: Some(service_data.len()),
+                ..Default::default()
+            },
+            CentralEvent::ServicesAdvertisement { server, services } => EventRecord {
+                kind: "services_advertisement",
+                peripheral_id: Some(*server),
+                value_len: Some(services.len()),
+                ..Default::default()
+            },
+            CentralEvent::DuplicateService { server, uuid } => EventRecord {
+                kind: "duplicate_service",
+                peripheral_id: Some(*server),
+                service_uuid: Some(*uuid),
+                ..Default::default()
+            },
+            CentralEvent::DescriptorsDiscovered {
+                server,
+                service_uuid,
+                characteristic_uuid,
+                descriptors,
+            } => EventRecord {
+                kind: "descriptors_discovered",
+                peripheral_id: Some(*server),
+                service_uuid: Some(*service_uuid),
+                characteristic_uuid: Some(*characteristic_uuid),
+                value_len: Some(descriptors.len()),
+                ..Default::default()
+            },
+            CentralEvent::ServicesDiscovered { server, services } => EventRecord {
+                kind: "services_discovered",
+                peripheral_id: Some(*server),
+                value_len: Some(services.len()),
+                ..Default::default()
+            },
+            CentralEvent::StateUpdate { state } => EventRecord {
+                kind: "state_update",
+                state: Some(state),
+                ..Default::default()
+            },
+            CentralEvent::ServicesModified {
+                server,
+                invalidated_services,
+            } => EventRecord {
+                kind: "services_modified",
+                peripheral_id: Some(*server),
+                value_len: Some(invalidated_services.len()),
+                ..Default::default()
+            },
+            CentralEvent::CharacteristicNotified {
+                server,
+                service_uuid,
+                characteristic_uuid,
+                value,
+                ..
+            } => EventRecord {
+                kind: "characteristic_notified",
+                peripheral_id: Some(*server),
+                service_uuid: Some(*service_uuid),
+                characteristic_uuid: Some(*characteristic_uuid),
+                value_len: Some(value.len()),
+                ..Default::default()
+            },
+            #[cfg(feature = "metrics")]
+            CentralEvent::CharacteristicWriteLatency {
+                server,
+                characteristic_uuid,
+                latency,
+            } => EventRecord {
+                kind: "characteristic_write_latency",
+                peripheral_id: Some(*server),
+                characteristic_uuid: Some(*characteristic_uuid),
+                latency: Some(*latency),
+                ..Default::default()
+            },
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -49,3 +314,95 @@ pub enum CentralState {
     PoweredOff = 4,
     PoweredOn = 5,
 }
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DisconnectReason {
+    // The app asked for the disconnect, reconnect logic should leave these alone
+    Requested,
+    // CoreBluetooth reported the disconnect without it being requested
+    LinkLoss,
+    // Connection supervision gave up after too many failed keep-alive reads
+    SupervisionTimeout,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovery_record_carries_name_and_rssi() {
+        let server = Uuid::from_u128(1);
+        let event = CentralEvent::DeviceDiscovered {
+            server,
+            name: "sensor".to_string(),
+            rssi: -60,
+        };
+        let expected = EventRecord {
+            kind: "device_discovered",
+            peripheral_id: Some(server),
+            name: Some("sensor"),
+            rssi: Some(-60),
+            ..Default::default()
+        };
+        assert_eq!(event.to_record(), expected);
+    }
+
+    #[test]
+    fn notification_record_carries_the_value_length() {
+        let event = CentralEvent::CharacteristicNotified {
+            server: Uuid::from_u128(1),
+            service_uuid: uuids::BATTERY,
+            characteristic_uuid: uuids::BATTERY_LEVEL,
+            value: [1, 2, 3].into_iter().collect(),
+            sequence: 4,
+            global_sequence: 9,
+        };
+        let record = event.to_record();
+        assert_eq!(record.kind, "characteristic_notified");
+        assert_eq!(record.service_uuid, Some(uuids::BATTERY));
+        assert_eq!(record.characteristic_uuid, Some(uuids::BATTERY_LEVEL));
+        assert_eq!(record.value_len, Some(3));
+        assert_eq!(record.name, None);
+    }
+
+    #[test]
+    fn disconnect_and_state_records_borrow_from_the_event() {
+        let event = CentralEvent::DeviceDisconnected {
+            server: Uuid::from_u128(1),
+            reason: DisconnectReason::LinkLoss,
+        };
+        assert_eq!(event.to_record().reason, Some(&DisconnectReason::LinkLoss));
+        let event = CentralEvent::StateUpdate {
+            state: CentralState::PoweredOn,
+        };
+        let record = event.to_record();
+        assert_eq!(record.kind, "state_update");
+        assert_eq!(record.state, Some(&CentralState::PoweredOn));
+        assert_eq!(record.peripheral_id, None);
+    }
+
+    // Eddystone frames are service data of 0xFEAA, the frame type is the first byte
+    #[test]
+    fn service_data_by_short_uuid() {
+        let eddystone = uuids::from_short(0xFEAA);
+        let service_data = ServiceData::from(HashMap::from([
+            (eddystone, vec![0x10, 0xEB]),
+            (uuids::BATTERY, vec![]),
+        ]));
+        assert_eq!(service_data.get_u16(0xFEAA), Some(&[0x10, 0xEB][..]));
+        assert_eq!(service_data.get_u16(0xFEAA), service_data.get(&eddystone));
+        assert_eq!(service_data.get_u16(0xFEAB), None);
+    }
+
+    #[test]
+    fn short_service_data_is_returned_as_is() {
+        let service_data = ServiceData::from(HashMap::from([
+            (uuids::from_short(0xFEAA), vec![0x20]),
+            (uuids::BATTERY, vec![]),
+        ]));
+        let frame = service_data.get_u16(0xFEAA).unwrap();
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame.get(1), None);
+        assert_eq!(service_data.get_u16(0x180F), Some(&[][..]));
+    }
+}
@@ -5,6 +5,8 @@ use crate::api::descriptor::{AttributePermission, Descriptor};
 #[derive(Debug, Ord, Eq, PartialEq, PartialOrd, Clone)]
 pub struct Characteristic {
     pub uuid: Uuid,
+    // Service the characteristic belongs to, only meaningful for discovered characteristics
+    pub service_uuid: Uuid,
     pub properties: Vec<CharacteristicProperty>,
     pub permissions: Vec<AttributePermission>,
     pub value: Option<Vec<u8>>,
@@ -15,6 +17,7 @@ impl Default for Characteristic {
     fn default() -> Self {
         Characteristic {
             uuid: Uuid::nil(),
+            service_uuid: Uuid::nil(),
             properties: vec![
                 CharacteristicProperty::Read,
                 CharacteristicProperty::Write,
@@ -30,6 +33,37 @@ impl Default for Characteristic {
     }
 }
 
+impl Characteristic {
+    pub fn can_read(&self) -> bool {
+        self.properties.contains(&CharacteristicProperty::Read)
+    }
+
+    // Any write type, with or without response
+    pub fn can_write(&self) -> bool {
+        self.properties.iter().any(|property| {
+            matches!(
+                property,
+                CharacteristicProperty::Write
+                    | CharacteristicProperty::WriteWithoutResponse
+                    | CharacteristicProperty::AuthenticatedSignedWrites
+            )
+        })
+    }
+
+    // Whether the characteristic can be subscribed to, through notifications or indications
+    pub fn can_notify(&self) -> bool {
+        self.properties.iter().any(|property| {
+            matches!(
+                property,
+                CharacteristicProperty::Notify
+                    | CharacteristicProperty::NotifyEncryptionRequired
+                    | CharacteristicProperty::Indicate
+                    | CharacteristicProperty::IndicateEncryptionRequired
+            )
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialOrd, Ord, Eq, PartialEq)]
 pub enum CharacteristicProperty {
     Broadcast,
@@ -48,4 +82,67 @@ pub enum CharacteristicProperty {
 pub enum CharacteristicWriteType {
     WriteWithoutResponse,
     WriteWithResponse,
+    // Write without response carrying an authentication signature, only valid for
+    // characteristics declaring `AuthenticatedSignedWrites`
+    SignedWriteWithoutResponse,
+}
+
+// Default ATT MTU used before an MTU exchange has taken place
+pub const DEFAULT_ATT_MTU: usize = 23;
+
+// Bytes of every ATT packet taken by the opcode and attribute handle
+pub const ATT_HEADER_SIZE: usize = 3;
+
+// Bytes of a signed write taken by the authentication signature
+pub const SIGNATURE_SIZE: usize = 12;
+
+// Maximum length of an attribute value, a long write can not go beyond this
+pub const MAX_ATTRIBUTE_VALUE_LENGTH: usize = 512;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_properties(properties: Vec<CharacteristicProperty>) -> Characteristic {
+        Characteristic {
+            properties,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn capabilities_follow_the_properties() {
+        let characteristic = with_properties(vec![CharacteristicProperty::Read]);
+        assert!(characteristic.can_read());
+        assert!(!characteristic.can_write());
+        assert!(!characteristic.can_notify());
+
+        let characteristic = with_properties(vec![CharacteristicProperty::Broadcast]);
+        assert!(!characteristic.can_read());
+        assert!(!characteristic.can_write());
+        assert!(!characteristic.can_notify());
+    }
+
+    #[test]
+    fn every_write_type_counts_as_writable() {
+        for property in [
+            CharacteristicProperty::Write,
+            CharacteristicProperty::WriteWithoutResponse,
+            CharacteristicProperty::AuthenticatedSignedWrites,
+        ] {
+            assert!(with_properties(vec![property]).can_write());
+        }
+    }
+
+    #[test]
+    fn indications_count_as_notifiable() {
+        for property in [
+            CharacteristicProperty::Notify,
+            CharacteristicProperty::NotifyEncryptionRequired,
+            CharacteristicProperty::Indicate,
+            CharacteristicProperty::IndicateEncryptionRequired,
+        ] {
+            assert!(with_properties(vec![property]).can_notify());
+        }
+    }
 }
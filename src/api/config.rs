@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+pub const DEFAULT_QUEUE_LABEL: &str = "CBqueue";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Keep-alive used to detect a peripheral that went out of range without a clean disconnect,
+    // disabled when None
+    pub supervision: Option<SupervisionConfig>,
+    // Default time to wait for a peripheral to answer a single operation
+    pub operation_timeout: Duration,
+    // Runtime driving the background BLE thread
+    pub runtime: RuntimeFlavor,
+    // What a write with response longer than an attribute value (512 bytes) does
+    pub long_writes: LongWritePolicy,
+    // Labels of the dispatch queues CoreBluetooth calls the delegates on, as shown in
+    // Instruments and crash reports
+    pub central_queue: String,
+    pub peripheral_queue: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            supervision: None,
+            operation_timeout: Duration::from_secs(5),
+            runtime: RuntimeFlavor::CurrentThread,
+            long_writes: LongWritePolicy::Reject,
+            central_queue: String::from(DEFAULT_QUEUE_LABEL),
+            peripheral_queue: String::from(DEFAULT_QUEUE_LABEL),
+        }
+    }
+}
+
+// NOTE: The manager loop and every peripheral task run on one LocalSet of the dedicated BLE
+// thread, CoreBluetooth objects can't move between threads. Extra workers of a multi threaded
+// runtime therefore stay idle, both flavors behave the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeFlavor {
+    CurrentThread,
+    MultiThread { workers: usize },
+}
+
+// NOTE: CoreBluetooth's long write stops at the 512 byte attribute limit, a longer value can't
+// be stored in one attribute at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LongWritePolicy {
+    // Fail with ErrorType::ValueTooLong
+    Reject,
+    // Write 512 byte chunks one after the other. This is NOT a Prepare/Execute long write: every
+    // chunk is a separate write at offset 0 replacing the previous value, so a plain attribute
+    // ends up holding the last chunk only. Only for peripherals whose own protocol consumes each
+    // write as the next fragment of a stream, e.g. firmware upload characteristics.
+    ApplicationFramed,
+}
+
+#[derive(Debug, Clone)]
+pub struct SupervisionConfig {
+    // How often the RSSI of a connected peripheral is read
+    pub interval: Duration,
+    // Consecutive failed (or unanswered) RSSI reads before the connection is cancelled
+    pub failure_threshold: u32,
+}
+
+impl Default for SupervisionConfig {
+    fn default() -> Self {
+        SupervisionConfig {
+            interval: Duration::from_secs(2),
+            failure_threshold: 3,
+        }
+    }
+}
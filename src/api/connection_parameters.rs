@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use crate::{Error, ErrorType, Result, util};
+
+// Value the peripheral uses for an interval it has no preference for
+const NO_PREFERENCE: u16 = 0xFFFF;
+
+// GAP Peripheral Preferred Connection Parameters (0x2A04), kept in their raw units
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConnectionParameters {
+    // Connection interval bounds in units of 1.25ms
+    pub min_interval: u16,
+    pub max_interval: u16,
+    // Connection events the peripheral may skip
+    pub latency: u16,
+    // Supervision timeout in units of 10ms
+    pub supervision_timeout: u16,
+}
+
+impl ConnectionParameters {
+    // Decode the 8 byte little endian characteristic value
+    pub fn from_bytes(value: &[u8]) -> Result<Self> {
+        let Some(bytes) = util::read_array::<8>(value, 0) else {
+            return Err(Error::from_string(
+                format!("Preferred connection parameters too short: {} bytes", value.len()),
+                ErrorType::InvalidValue,
+            ));
+        };
+        let field = |index: usize| u16::from_le_bytes([bytes[index], bytes[index + 1]]);
+        Ok(ConnectionParameters {
+            min_interval: field(0),
+            max_interval: field(2),
+            latency: field(4),
+            supervision_timeout: field(6),
+        })
+    }
+
+    // None when the peripheral has no preference
+    pub fn min_interval_duration(&self) -> Option<Duration> {
+        interval_duration(self.min_interval)
+    }
+
+    pub fn max_interval_duration(&self) -> Option<Duration> {
+        interval_duration(self.max_interval)
+    }
+
+    pub fn supervision_timeout_duration(&self) -> Option<Duration> {
+        match self.supervision_timeout {
+            NO_PREFERENCE => None,
+            timeout => Some(Duration::from_millis(timeout as u64 * 10)),
+        }
+    }
+}
+
+fn interval_duration(interval: u16) -> Option<Duration> {
+    match interval {
+        NO_PREFERENCE => None,
+        interval => Some(Duration::from_micros(interval as u64 * 1250)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eight_bytes_are_decoded() {
+        // 7.5ms to 15ms, no latency, 4s supervision timeout
+        let parameters = ConnectionParameters::from_bytes(&[6, 0, 12, 0, 0, 0, 0x90, 0x01]).unwrap();
+        assert_eq!(
+            parameters,
+            ConnectionParameters {
+                min_interval: 6,
+                max_interval: 12,
+                latency: 0,
+                supervision_timeout: 400,
+            }
+        );
+        assert_eq!(parameters.min_interval_duration(), Some(Duration::from_micros(7500)));
+        assert_eq!(parameters.max_interval_duration(), Some(Duration::from_millis(15)));
+        assert_eq!(parameters.supervision_timeout_duration(), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn no_preference_has_no_duration() {
+        let parameters = ConnectionParameters::from_bytes(&[0xFF; 8]).unwrap();
+        assert_eq!(parameters.min_interval_duration(), None);
+        assert_eq!(parameters.max_interval_duration(), None);
+        assert_eq!(parameters.supervision_timeout_duration(), None);
+    }
+
+    #[test]
+    fn short_value_is_rejected() {
+        let error = ConnectionParameters::from_bytes(&[6, 0, 12, 0, 0, 0, 0x90]).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InvalidValue);
+        assert!(ConnectionParameters::from_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{Error, ErrorType, Result, util};
+
+// Day of Week as encoded by the Bluetooth SIG, 0 means the day is unknown
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DayOfWeek {
+    Unknown,
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl From<u8> for DayOfWeek {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => DayOfWeek::Monday,
+            2 => DayOfWeek::Tuesday,
+            3 => DayOfWeek::Wednesday,
+            4 => DayOfWeek::Thursday,
+            5 => DayOfWeek::Friday,
+            6 => DayOfWeek::Saturday,
+            7 => DayOfWeek::Sunday,
+            _ => DayOfWeek::Unknown,
+        }
+    }
+}
+
+// Current Time characteristic (0x2A2B) of the Current Time Service. A year, month or day of 0
+// means the peripheral doesn't know it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CurrentTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub day_of_week: DayOfWeek,
+    // Fractions of a second in 1/256th units
+    pub fractions256: u8,
+    // Bit field of why the time was last adjusted (manual, reference update, time zone, DST)
+    pub adjust_reason: u8,
+}
+
+impl CurrentTime {
+    // Decode the 10 byte characteristic value, the year is little endian
+    pub fn from_bytes(value: &[u8]) -> Result<Self> {
+        let Some(bytes) = util::read_array::<10>(value, 0) else {
+            return Err(Error::from_string(
+                format!("Current time value too short: {} bytes", value.len()),
+                ErrorType::InvalidValue,
+            ));
+        };
+        Ok(CurrentTime {
+            year: u16::from_le_bytes([bytes[0], bytes[1]]),
+            month: bytes[2],
+            day: bytes[3],
+            hours: bytes[4],
+            minutes: bytes[5],
+            seconds: bytes[6],
+            day_of_week: DayOfWeek::from(bytes[7]),
+            fractions256: bytes[8],
+            adjust_reason: bytes[9],
+        })
+    }
+
+    // The fractions256 field as nanoseconds
+    pub fn subsec_nanos(&self) -> u32 {
+        (self.fractions256 as u64 * 1_000_000_000 / 256) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_a_current_time_frame() {
+        // 2013-09-17 13:45:30 and 128/256 s, Tuesday, adjusted manually
+        let frame = [0xDD, 0x07, 0x09, 0x11, 0x0D, 0x2D, 0x1E, 0x02, 0x80, 0x01];
+        let time = CurrentTime::from_bytes(&frame).unwrap();
+        assert_eq!(
+            time,
+            CurrentTime {
+                year: 2013,
+                month: 9,
+                day: 17,
+                hours: 13,
+                minutes: 45,
+                seconds: 30,
+                day_of_week: DayOfWeek::Tuesday,
+                fractions256: 128,
+                adjust_reason: 1,
+            }
+        );
+        assert_eq!(time.subsec_nanos(), 500_000_000);
+    }
+
+    #[test]
+    fn short_frame_is_an_error() {
+        let error = CurrentTime::from_bytes(&[0xDD, 0x07, 0x09]).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InvalidValue);
+    }
+}
@@ -5,6 +5,10 @@ use crate::api::characteristic::CharacteristicProperty;
 #[derive(Debug, Ord, Clone, PartialOrd, PartialEq, Eq)]
 pub struct Descriptor {
     pub uuid: Uuid,
+    // Service and characteristic the descriptor belongs to, only meaningful for discovered
+    // descriptors
+    pub service_uuid: Uuid,
+    pub characteristic_uuid: Uuid,
     pub properties: Vec<CharacteristicProperty>,
     pub permissions: Vec<AttributePermission>,
     pub value: Option<Vec<u8>>,
@@ -14,6 +18,8 @@ impl Default for Descriptor {
     fn default() -> Self {
         Descriptor {
             uuid: Uuid::nil(),
+            service_uuid: Uuid::nil(),
+            characteristic_uuid: Uuid::nil(),
             properties: vec![
                 CharacteristicProperty::Read,
                 CharacteristicProperty::Write,
@@ -0,0 +1,10 @@
+// Values of the Device Information Service (0x180A), fields the device doesn't expose are None
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeviceInformation {
+    pub manufacturer_name: Option<String>,
+    pub model_number: Option<String>,
+    pub serial_number: Option<String>,
+    pub hardware_revision: Option<String>,
+    pub firmware_revision: Option<String>,
+    pub software_revision: Option<String>,
+}
@@ -0,0 +1,50 @@
+// Little-endian encoders for characteristic values, GATT transmits multi-byte numbers LSB first
+
+pub fn u8(value: u8) -> Vec<u8> {
+    vec![value]
+}
+
+pub fn i8(value: i8) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn u16_le(value: u16) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn i16_le(value: i16) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn u32_le(value: u32) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn i32_le(value: i32) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+pub fn f32_le(value: f32) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::read_array;
+
+    #[test]
+    fn values_round_trip_through_the_readers() {
+        assert_eq!(u8(0xAB), vec![0xAB]);
+        assert_eq!(i8(-2), vec![0xFE]);
+        assert_eq!(u16_le(0x1234), vec![0x34, 0x12]);
+        let value = i16_le(-300);
+        assert_eq!(i16::from_le_bytes(read_array(&value, 0).unwrap()), -300);
+        let value = u32_le(0xDEAD_BEEF);
+        assert_eq!(u32::from_le_bytes(read_array(&value, 0).unwrap()), 0xDEAD_BEEF);
+        let value = i32_le(-70_000);
+        assert_eq!(i32::from_le_bytes(read_array(&value, 0).unwrap()), -70_000);
+        let value = f32_le(21.5);
+        assert_eq!(f32::from_le_bytes(read_array(&value, 0).unwrap()), 21.5);
+    }
+}
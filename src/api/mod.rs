@@ -5,3 +5,14 @@ pub mod descriptor;
 pub mod central_event;
 pub mod central;
 pub mod peripheral;
+pub mod config;
+pub mod uuids;
+pub mod appearance;
+pub mod encode;
+pub mod device_info;
+pub mod connection_parameters;
+pub mod current_time;
+pub mod advertising_flags;
+pub mod adapter;
+pub mod presentation_format;
+pub mod advertisement;
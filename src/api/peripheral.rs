@@ -1,10 +1,14 @@
 
+use std::time::Duration;
+
 use async_trait::async_trait;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
 use crate::Result;
-use crate::api::peripheral_event::PeripheralEvent;
+use crate::api::advertisement::AdvertisementData;
+use crate::api::config::Config;
+use crate::api::peripheral_event::{PeripheralEvent, PeripheralRequestResponse, RequestHandlers};
 use crate::api::service::Service;
 
 #[async_trait]
@@ -13,15 +17,69 @@ pub trait PeripheralManager: Send + Sync {
 
     async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self::PeripheralManager>;
 
+    async fn new_with_config(
+        sender_tx: Sender<PeripheralEvent>,
+        config: Config,
+    ) -> Result<Self::PeripheralManager>;
+
     async fn is_powered(&mut self) -> Result<bool>;
 
+    // Wait for the adapter to power on, see CentralManager::wait_until_powered_on
+    async fn wait_until_powered_on(&mut self, timeout: Duration) -> Result<()>;
+
     async fn is_advertising(&mut self) -> Result<bool>;
 
-    async fn start_advertising(&mut self, name: &str, uuids: &[Uuid]) -> Result<()>;
+    // Without a name the one given to set_local_name or the last start_advertising is advertised
+    async fn start_advertising(&mut self, name: Option<&str>, uuids: &[Uuid]) -> Result<()>;
 
     async fn stop_advertising(&mut self) -> Result<()>;
 
-    async fn add_service(&mut self, service: &Service) -> Result<()>;
+    // Advertise without any GATT service, no add_service needed. Fails with
+    // AdvertisementTooLarge when `data` doesn't fit a legacy advertising payload.
+    async fn advertise_beacon(&mut self, data: AdvertisementData) -> Result<()>;
+
+    // Local name of the last start_advertising or set_local_name, None before either
+    async fn local_name(&mut self) -> Result<Option<String>>;
+
+    // Change the advertised local name, re-advertising with the same services straight away when
+    // advertising already. If the restarted advertisement is refused the error is returned and
+    // advertising is left stopped, see is_advertising.
+    async fn set_local_name(&mut self, name: &str) -> Result<()>;
 
+    async fn add_service(&mut self, service: &Service) -> Result<()> {
+        self.add_service_with_handlers(service, RequestHandlers::default())
+            .await
+    }
+
+    // Add a service whose requests are answered by `handlers` instead of events
+    async fn add_service_with_handlers(
+        &mut self,
+        service: &Service,
+        handlers: RequestHandlers,
+    ) -> Result<()>;
+
+    // Add several services one after the other, each waiting for didAddService before the next
+    // is added. Stops at and returns the first error.
+    async fn add_services(&mut self, services: &[Service]) -> Result<()> {
+        for service in services {
+            self.add_service(service).await?;
+        }
+        Ok(())
+    }
+
+    // Set the value and notify the subscribed centrals, resolving once CoreBluetooth took the
+    // notification. Fails with ErrorType::ValueTooLong when a subscriber can't take the value,
+    // without subscribers any length is stored.
     async fn update_characteristic(&mut self, characteristic: Uuid, value: Vec<u8>) -> Result<()>;
+
+    // Largest notification payload every subscribed central accepts, follows centrals as they
+    // (un)subscribe. Longer updates fail with ErrorType::ValueTooLong.
+    async fn max_notify_length(&mut self) -> Result<usize>;
+
+    // Answer a ReadRequest or WriteRequest event, can be called any time after the event
+    async fn respond_to_request(
+        &mut self,
+        id: u64,
+        response: PeripheralRequestResponse,
+    ) -> Result<()>;
 }
@@ -1,4 +1,5 @@
-use tokio::sync::oneshot::Sender;
+use std::collections::HashMap;
+
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -6,21 +7,45 @@ pub enum PeripheralEvent {
     StateUpdate {
         is_powered: bool,
     },
+    // Advertising started (or failed to, with the CoreBluetooth error) or was stopped
+    AdvertisingStateChanged {
+        advertising: bool,
+        error: Option<String>,
+    },
     CharacteristicSubscriptionUpdate {
         request: PeripheralRequest,
         subscribed: bool,
+        // Last value set through add_service or update_characteristic, so a new subscriber can
+        // be sent the current state straight away
+        value: Option<Vec<u8>>,
     },
+    // Requests stay pending until answered with PeripheralManager::respond_to_request using
+    // their id, the CoreBluetooth queue is never blocked waiting on the app.
     ReadRequest {
+        id: u64,
         request: PeripheralRequest,
         offset: u64,
-        responder: Sender<ReadRequestResponse>
     },
     WriteRequest {
+        id: u64,
         request: PeripheralRequest,
         value: Vec<u8>,
         offset: u64,
-        responder: Sender<WriteRequestResponse> 
     },
+    // A long or reliable write the central split over several requests. It applies as a whole:
+    // check every part, apply all of them or none and answer once with respond_to_request.
+    QueuedWriteRequest {
+        id: u64,
+        writes: Vec<QueuedWrite>,
+    },
+}
+
+// One part of a QueuedWriteRequest, in the order the central sent them
+#[derive(Debug, Clone)]
+pub struct QueuedWrite {
+    pub request: PeripheralRequest,
+    pub value: Vec<u8>,
+    pub offset: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +53,33 @@ pub struct PeripheralRequest {
     pub client: String,
     pub service: Uuid,
     pub characteristic: Uuid,
+    // Largest value this central accepts in a single notification or indication
+    // (maximumUpdateValueLength), for sizing responses to it
+    pub max_value_length: usize,
+}
+
+// Produces the value of a read in place of a ReadRequest event, given the read offset.
+// NOTE: Runs on the peripheral manager thread and holds up every other request while it runs.
+pub type ReadHandler = Box<dyn FnMut(u64, &PeripheralRequest) -> ReadRequestResponse + Send>;
+
+// Applies a write in place of a WriteRequest event, given the written value and its offset.
+// Runs on the peripheral manager thread like ReadHandler.
+pub type WriteHandler =
+    Box<dyn FnMut(&[u8], u64, &PeripheralRequest) -> WriteRequestResponse + Send>;
+
+// Checks a write before any part of it is applied, same arguments as WriteHandler. Every part of
+// a queued write is checked first, so a refused part leaves all characteristics untouched.
+pub type WriteValidator =
+    Box<dyn FnMut(&[u8], u64, &PeripheralRequest) -> RequestResponse + Send>;
+
+// Request handlers of a service's characteristics, keyed by characteristic UUID. Requests for
+// characteristics without a handler are still reported as events, writes without a validator
+// are always accepted.
+#[derive(Default)]
+pub struct RequestHandlers {
+    pub read: HashMap<Uuid, ReadHandler>,
+    pub write: HashMap<Uuid, WriteHandler>,
+    pub validate_write: HashMap<Uuid, WriteValidator>,
 }
 
 #[derive(Debug)]
@@ -41,11 +93,43 @@ pub struct WriteRequestResponse {
     pub response: RequestResponse,
 }
 
+#[derive(Debug)]
+pub enum PeripheralRequestResponse {
+    Read(ReadRequestResponse),
+    Write(WriteRequestResponse),
+}
+
+impl From<ReadRequestResponse> for PeripheralRequestResponse {
+    fn from(response: ReadRequestResponse) -> Self {
+        PeripheralRequestResponse::Read(response)
+    }
+}
+
+impl From<WriteRequestResponse> for PeripheralRequestResponse {
+    fn from(response: WriteRequestResponse) -> Self {
+        PeripheralRequestResponse::Write(response)
+    }
+}
+
+// ATT status returned to the central, covers every CBATTError code
 #[derive(Debug, PartialEq)]
 pub enum RequestResponse {
     Success,
     InvalidHandle,
+    ReadNotPermitted,
+    WriteNotPermitted,
+    InvalidPdu,
+    InsufficientAuthentication,
     RequestNotSupported,
     InvalidOffset,
+    InsufficientAuthorization,
+    PrepareQueueFull,
+    AttributeNotFound,
+    AttributeNotLong,
+    InsufficientEncryptionKeySize,
+    InvalidAttributeValueLength,
     UnlikelyError,
+    InsufficientEncryption,
+    UnsupportedGroupType,
+    InsufficientResources,
 }
@@ -0,0 +1,136 @@
+use crate::{Error, ErrorType, Result, util};
+
+// Characteristic Presentation Format descriptor (0x2904), describing how to render the value
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PresentationFormat {
+    // Format of the value, e.g. 0x06 for uint16 (Assigned Numbers, section 2.4.1)
+    pub format: u8,
+    // Base 10 exponent applied to integer values, 365 with -1 reads as 36.5
+    pub exponent: i8,
+    // Unit UUID short form, e.g. 0x272F for degrees Celsius
+    pub unit: u16,
+    pub namespace: u8,
+    pub description: u16,
+}
+
+impl PresentationFormat {
+    // Decode the 7 byte little endian descriptor value
+    pub fn from_bytes(value: &[u8]) -> Result<Self> {
+        let Some(bytes) = util::read_array::<7>(value, 0) else {
+            return Err(Error::from_string(
+                format!("Presentation format too short: {} bytes", value.len()),
+                ErrorType::InvalidValue,
+            ));
+        };
+        Ok(PresentationFormat {
+            format: bytes[0],
+            exponent: bytes[1] as i8,
+            unit: u16::from_le_bytes([bytes[2], bytes[3]]),
+            namespace: bytes[4],
+            description: u16::from_le_bytes([bytes[5], bytes[6]]),
+        })
+    }
+
+    // Render `value` with its unit, e.g. "36.5 °C". None when the format isn't supported or the
+    // value is too short for it, IEEE 11073 floats and the other exotic formats aren't decoded.
+    pub fn format_value(&self, value: &[u8]) -> Option<String> {
+        let number = match self.format {
+            0x01 => return Some(self.with_unit((*value.first()? & 0x01 == 1).to_string())),
+            0x04 => *value.first()? as i128,
+            0x06 => u16::from_le_bytes(util::read_array(value, 0)?) as i128,
+            0x07 => unsigned_le(&util::read_array::<3>(value, 0)?) as i128,
+            0x08 => u32::from_le_bytes(util::read_array(value, 0)?) as i128,
+            0x0A => u64::from_le_bytes(util::read_array(value, 0)?) as i128,
+            0x0C => *value.first()? as i8 as i128,
+            0x0E => i16::from_le_bytes(util::read_array(value, 0)?) as i128,
+            // Sign extend the 24 bit value
+            0x0F => ((unsigned_le(&util::read_array::<3>(value, 0)?) << 8) as i32 >> 8) as i128,
+            0x10 => i32::from_le_bytes(util::read_array(value, 0)?) as i128,
+            0x12 => i64::from_le_bytes(util::read_array(value, 0)?) as i128,
+            0x14 => return Some(self.with_unit(f32::from_le_bytes(util::read_array(value, 0)?).to_string())),
+            0x15 => return Some(self.with_unit(f64::from_le_bytes(util::read_array(value, 0)?).to_string())),
+            0x19 => return Some(self.with_unit(String::from_utf8_lossy(value).into_owned())),
+            _ => return None,
+        };
+        let rendered = if self.exponent < 0 {
+            let decimals = self.exponent.unsigned_abs() as usize;
+            format!("{:.*}", decimals, number as f64 * 10f64.powi(self.exponent as i32))
+        } else {
+            number.checked_mul(10i128.checked_pow(self.exponent as u32)?)?.to_string()
+        };
+        Some(self.with_unit(rendered))
+    }
+
+    fn with_unit(&self, rendered: String) -> String {
+        match unit_symbol(self.unit) {
+            Some(symbol) => format!("{} {}", rendered, symbol),
+            None => rendered,
+        }
+    }
+}
+
+// Symbol of the commonly used units, None for unitless and anything not listed
+pub fn unit_symbol(unit: u16) -> Option<&'static str> {
+    let symbol = match unit {
+        0x2701 => "m",
+        0x2702 => "kg",
+        0x2703 => "s",
+        0x2704 => "A",
+        0x2705 => "K",
+        0x2724 => "Pa",
+        0x2725 => "J",
+        0x2726 => "W",
+        0x2728 => "V",
+        0x272F => "°C",
+        0x2731 => "lx",
+        0x27A7 => "bpm",
+        0x27AC => "°F",
+        0x27AD => "%",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn unsigned_le(bytes: &[u8; 3]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: u8, exponent: i8, unit: u16) -> PresentationFormat {
+        PresentationFormat {
+            format,
+            exponent,
+            unit,
+            namespace: 1,
+            description: 0,
+        }
+    }
+
+    #[test]
+    fn negative_exponent_renders_decimals_with_the_unit() {
+        // sint16 365 with exponent -1 in degrees Celsius
+        let celsius = format(0x0E, -1, 0x272F);
+        assert_eq!(celsius.format_value(&365i16.to_le_bytes()).unwrap(), "36.5 °C");
+        assert_eq!(celsius.format_value(&(-42i16).to_le_bytes()).unwrap(), "-4.2 °C");
+    }
+
+    #[test]
+    fn positive_exponent_scales_up() {
+        let percent = format(0x04, 2, 0x27AD);
+        assert_eq!(percent.format_value(&[3]).unwrap(), "300 %");
+    }
+
+    #[test]
+    fn unknown_unit_renders_the_bare_value() {
+        assert_eq!(format(0x06, 0, 0x2700).format_value(&[0x34, 0x12]).unwrap(), "4660");
+    }
+
+    #[test]
+    fn short_value_or_unknown_format_renders_nothing() {
+        assert_eq!(format(0x08, 0, 0x2700).format_value(&[1, 2, 3]), None);
+        assert_eq!(format(0x16, 0, 0x2700).format_value(&[1, 2]), None);
+    }
+}
@@ -1,12 +1,24 @@
+use std::cmp::Ordering;
+
 use uuid::Uuid;
 
 use crate::api::characteristic::Characteristic;
+#[cfg(feature = "testing")]
+use crate::api::{characteristic::CharacteristicProperty, descriptor::AttributePermission};
+
+#[cfg(feature = "testing")]
+pub const ECHO_SERVICE_UUID: Uuid = Uuid::from_u128(0x6e1c0001_5f2b_4a3c_9d4e_8b7a6c5d4e3f);
+#[cfg(feature = "testing")]
+pub const ECHO_CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x6e1c0002_5f2b_4a3c_9d4e_8b7a6c5d4e3f);
 
 #[derive(Debug, Clone)]
 pub struct Service {
     pub uuid: Uuid,
     pub primary: bool,
     pub characteristics: Vec<Characteristic>,
+    // UUIDs of the services included by this service, as reported by
+    // didDiscoverIncludedServicesForService
+    pub included_services: Vec<Uuid>,
 }
 
 impl Default for Service {
@@ -15,6 +27,52 @@ impl Default for Service {
             uuid: Uuid::nil(),
             primary: true,
             characteristics: Vec::new(),
+            included_services: Vec::new(),
         }
     }
 }
+
+#[cfg(feature = "testing")]
+impl Service {
+    // Loopback service for integration testing, the peripheral is expected to notify every value
+    // written to the echo characteristic back to its subscribers (see examples/echo.rs)
+    pub fn echo_test() -> Self {
+        Service {
+            uuid: ECHO_SERVICE_UUID,
+            primary: true,
+            characteristics: vec![Characteristic {
+                uuid: ECHO_CHARACTERISTIC_UUID,
+                properties: vec![
+                    CharacteristicProperty::Write,
+                    CharacteristicProperty::WriteWithoutResponse,
+                    CharacteristicProperty::Notify,
+                ],
+                permissions: vec![AttributePermission::Writeable],
+                ..Default::default()
+            }],
+            included_services: Vec::new(),
+        }
+    }
+}
+
+// NOTE: A Service is identified by its UUID only, so two services with the same UUID compare
+// equal regardless of their characteristics or included services.
+impl PartialEq for Service {
+    fn eq(&self, other: &Self) -> bool {
+        self.uuid == other.uuid
+    }
+}
+
+impl Eq for Service {}
+
+impl PartialOrd for Service {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Service {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.uuid.cmp(&other.uuid)
+    }
+}
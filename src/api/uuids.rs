@@ -0,0 +1,79 @@
+use uuid::Uuid;
+
+// NOTE: Bluetooth SIG assigned numbers are 16 bit short UUIDs expanded onto the base UUID
+// 0000xxxx-0000-1000-8000-00805F9B34FB
+const BLUETOOTH_BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805F9B34FB;
+
+pub const fn from_short(short_uuid: u16) -> Uuid {
+    Uuid::from_u128(BLUETOOTH_BASE_UUID | ((short_uuid as u128) << 96))
+}
+
+// Services
+pub const GENERIC_ACCESS: Uuid = from_short(0x1800);
+pub const CURRENT_TIME_SERVICE: Uuid = from_short(0x1805);
+pub const DEVICE_INFORMATION: Uuid = from_short(0x180A);
+pub const HEART_RATE: Uuid = from_short(0x180D);
+pub const BATTERY: Uuid = from_short(0x180F);
+
+// Characteristics
+pub const DEVICE_NAME: Uuid = from_short(0x2A00);
+pub const APPEARANCE: Uuid = from_short(0x2A01);
+pub const PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS: Uuid = from_short(0x2A04);
+pub const MODEL_NUMBER_STRING: Uuid = from_short(0x2A24);
+pub const SERIAL_NUMBER_STRING: Uuid = from_short(0x2A25);
+pub const FIRMWARE_REVISION_STRING: Uuid = from_short(0x2A26);
+pub const HARDWARE_REVISION_STRING: Uuid = from_short(0x2A27);
+pub const SOFTWARE_REVISION_STRING: Uuid = from_short(0x2A28);
+pub const BATTERY_LEVEL: Uuid = from_short(0x2A19);
+pub const MANUFACTURER_NAME_STRING: Uuid = from_short(0x2A29);
+pub const CURRENT_TIME: Uuid = from_short(0x2A2B);
+
+// Descriptors
+pub const CHARACTERISTIC_USER_DESCRIPTION: Uuid = from_short(0x2901);
+pub const CLIENT_CHARACTERISTIC_CONFIGURATION: Uuid = from_short(0x2902);
+pub const SERVER_CHARACTERISTIC_CONFIGURATION: Uuid = from_short(0x2903);
+pub const CHARACTERISTIC_PRESENTATION_FORMAT: Uuid = from_short(0x2904);
+
+// Readable name of the well-known UUIDs above
+pub fn short_name(uuid: Uuid) -> Option<&'static str> {
+    let name = match uuid {
+        GENERIC_ACCESS => "Generic Access",
+        CURRENT_TIME_SERVICE => "Current Time Service",
+        DEVICE_INFORMATION => "Device Information",
+        HEART_RATE => "Heart Rate",
+        BATTERY => "Battery",
+        DEVICE_NAME => "Device Name",
+        APPEARANCE => "Appearance",
+        PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS => "Peripheral Preferred Connection Parameters",
+        MODEL_NUMBER_STRING => "Model Number String",
+        SERIAL_NUMBER_STRING => "Serial Number String",
+        FIRMWARE_REVISION_STRING => "Firmware Revision String",
+        HARDWARE_REVISION_STRING => "Hardware Revision String",
+        SOFTWARE_REVISION_STRING => "Software Revision String",
+        BATTERY_LEVEL => "Battery Level",
+        MANUFACTURER_NAME_STRING => "Manufacturer Name String",
+        CURRENT_TIME => "Current Time",
+        CHARACTERISTIC_USER_DESCRIPTION => "Characteristic User Description",
+        CLIENT_CHARACTERISTIC_CONFIGURATION => "Client Characteristic Configuration",
+        SERVER_CHARACTERISTIC_CONFIGURATION => "Server Characteristic Configuration",
+        CHARACTERISTIC_PRESENTATION_FORMAT => "Characteristic Presentation Format",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// Services service_by_name knows about
+const NAMED_SERVICES: [Uuid; 5] = [
+    GENERIC_ACCESS,
+    CURRENT_TIME_SERVICE,
+    DEVICE_INFORMATION,
+    HEART_RATE,
+    BATTERY,
+];
+
+// Well-known service by its short_name, ignoring case
+pub fn service_by_name(name: &str) -> Option<Uuid> {
+    NAMED_SERVICES.into_iter().find(|uuid| {
+        short_name(*uuid).is_some_and(|service_name| service_name.eq_ignore_ascii_case(name))
+    })
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream, StreamExt};
+use tokio::sync::{oneshot, watch};
+
+use crate::{
+    Result,
+    api::{adapter::Authorization, central_event::CentralState, config::Config},
+    corebluetooth::objc_bindings::adapter_cb::{self, run_adapter_thread},
+};
+
+// Bluetooth availability on its own, for checking at startup before creating a Central or
+// Peripheral. Backed by a central manager that only listens for state updates.
+pub struct Adapter {
+    state_rx: watch::Receiver<CentralState>,
+    // Dropped with the adapter, which ends the background thread
+    _shutdown_tx: oneshot::Sender<()>,
+}
+
+impl Adapter {
+    pub fn new() -> Result<Self> {
+        Self::new_with_config(Config::default())
+    }
+
+    // Only the central dispatch queue label of the config is used
+    pub fn new_with_config(config: Config) -> Result<Self> {
+        let (state_tx, state_rx) = watch::channel(CentralState::Unknown);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        run_adapter_thread(state_tx, shutdown_rx, &config.central_queue);
+        Ok(Adapter {
+            state_rx,
+            _shutdown_tx: shutdown_tx,
+        })
+    }
+
+    // Latest state, Unknown until CoreBluetooth reported the first one
+    pub fn state(&self) -> CentralState {
+        self.state_rx.borrow().clone()
+    }
+
+    pub fn authorization(&self) -> Authorization {
+        adapter_cb::authorization()
+    }
+
+    // The current state followed by every change, ends when the adapter is dropped
+    pub fn state_stream(&self) -> BoxStream<'static, CentralState> {
+        let mut state_rx = self.state_rx.clone();
+        state_rx.mark_changed();
+        stream::unfold(state_rx, |mut state_rx| async move {
+            state_rx.changed().await.ok()?;
+            let state = state_rx.borrow_and_update().clone();
+            Some((state, state_rx))
+        })
+        .boxed()
+    }
+
+    pub async fn wait_until_powered_on(&self, timeout: Duration) -> Result<()> {
+        super::wait_until_powered_on(self.state_rx.clone(), timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn state_stream_reports_transitions_until_the_thread_ends() {
+        let (state_tx, state_rx) = watch::channel(CentralState::Unknown);
+        let (shutdown_tx, _shutdown_rx) = oneshot::channel();
+        let adapter = Adapter {
+            state_rx,
+            _shutdown_tx: shutdown_tx,
+        };
+        let mut states = adapter.state_stream();
+        assert_eq!(states.next().await, Some(CentralState::Unknown));
+        state_tx.send_replace(CentralState::PoweredOff);
+        assert_eq!(states.next().await, Some(CentralState::PoweredOff));
+        state_tx.send_replace(CentralState::PoweredOn);
+        assert_eq!(states.next().await, Some(CentralState::PoweredOn));
+        assert_eq!(adapter.state(), CentralState::PoweredOn);
+        // The background thread drops the sender once the adapter is dropped
+        drop(state_tx);
+        assert_eq!(states.next().await, None);
+    }
+}
@@ -1,22 +1,57 @@
 use std::collections::{BTreeSet, HashMap};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
-use tokio::sync::{mpsc::Sender, oneshot};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use tokio::sync::{
+    broadcast,
+    mpsc::{self, Sender, error::TrySendError},
+    oneshot, watch,
+};
+use tokio::runtime::Handle;
+use tokio::time;
 use uuid::Uuid;
 
 use crate::{
-    Error, Result, api::{
-        central::{CentralManager, PeripheralId, PeripheralRemote, ScanFilter},
-        central_event::{CentralEvent, CentralState},
-        characteristic::{Characteristic, CharacteristicProperty, CharacteristicWriteType},
+    Error, ErrorType, Result, api::{
+        central::{
+            BackpressureStrategy, CentralManager, ConnectOptions, ConnectionPriority,
+            DiscoveredDevice, PeripheralId, PeripheralNames, PeripheralRemote, ProvisionError,
+            ProvisionStep, Provisioned, RssiRanking, ScanFilter, SubscribeOptions,
+        },
+        central_event::{CentralEvent, CentralState, Payload, payload_into_vec},
+        config::{Config, LongWritePolicy},
+        characteristic::{
+            ATT_HEADER_SIZE, Characteristic, CharacteristicProperty, CharacteristicWriteType,
+            DEFAULT_ATT_MTU, MAX_ATTRIBUTE_VALUE_LENGTH, SIGNATURE_SIZE,
+        },
         descriptor::Descriptor,
         service::Service,
-    }
+    },
+    corebluetooth::objc_bindings::central_manager_cb::run_central_thread,
 };
 
+// How long provision scans for the nearest device
+const PROVISION_SCAN_WINDOW: Duration = Duration::from_secs(5);
+
 pub struct Central {
-    peripherals: DashMap<PeripheralId, PeripheralRemote>,
+    peripherals: HashMap<PeripheralId, Peripheral>,
     command_tx: Sender<CentralManagerCommand>,
+    // Shared by every Peripheral handle, the background central routes commands by peripheral UUID
+    peripheral_command_tx: Sender<PeripheralRemoteCommand>,
+    // Last advertisement time of every peripheral seen, updated by the background central
+    last_seen: Arc<Mutex<HashMap<PeripheralId, Instant>>>,
+    // Latest state reported by CoreBluetooth
+    state_rx: watch::Receiver<CentralState>,
+    // Fan out of every CentralEvent alongside the sender given at creation
+    events_tx: broadcast::Sender<CentralEvent>,
+    config: Config,
 }
 
 #[async_trait]
@@ -25,23 +60,59 @@ impl CentralManager for Central {
     type Peripheral = Peripheral;
 
     async fn new(sender_tx: Sender<CentralEvent>) -> Result<Self> {
-        todo!()
+        Self::new_with_config(sender_tx, Config::default()).await
+    }
+
+    async fn new_with_config(sender_tx: Sender<CentralEvent>, config: Config) -> Result<Self> {
+        Self::start(sender_tx, config, None)
     }
 
     async fn start_scan(&mut self, filter: ScanFilter) -> Result<bool> {
-        todo!()
+        self.command_tx
+            .send(CentralManagerCommand::StartScanning {
+                filter,
+                discovery_tx: None,
+                allow_duplicates: false,
+            })
+            .await?;
+        Ok(true)
     }
 
     async fn stop_scan(&mut self) -> Result<()> {
-        todo!()
+        self.command_tx
+            .send(CentralManagerCommand::StopScanning)
+            .await?;
+        Ok(())
     }
 
     async fn peripherals(&mut self) -> Result<Vec<Self::Peripheral>> {
-        todo!()
+        let (responder, receiver) = oneshot::channel();
+        self.command_tx
+            .send(CentralManagerCommand::GetPeripherals {
+                connected_only: false,
+                responder,
+            })
+            .await?;
+        let known = receiver.await?;
+        Ok(known
+            .into_iter()
+            .map(|(uuid, mtu)| self.handle(uuid.into(), mtu))
+            .collect())
     }
 
+    // Only a discovered peripheral can be connected, the background central holds on to the
+    // CBPeripheral from its advertisement until the peripheral is forgotten
     async fn peripheral(&mut self, address: &PeripheralId) -> Result<Self::Peripheral> {
-        todo!()
+        let (responder, receiver) = oneshot::channel();
+        self.command_tx
+            .send(CentralManagerCommand::GetPeripheral {
+                peripheral_uuid: address.uuid(),
+                retrieve: false,
+                responder,
+            })
+            .await?;
+        let mtu = receiver.await??;
+        Ok(self.handle(address.clone(), mtu))
     }
 
     async fn adapter_info(&mut self) -> Result<String> {
@@ -49,12 +120,447 @@ impl CentralManager for Central {
     }
 
     async fn adapter_state(&mut self) -> Result<CentralState> {
-        todo!()
+        Ok(self.state_rx.borrow().clone())
+    }
+
+    async fn wait_until_powered_on(&mut self, timeout: Duration) -> Result<()> {
+        super::wait_until_powered_on(self.state_rx.clone(), timeout).await
+    }
+}
+
+impl Central {
+    // Same as new_with_config but drives the background central on the runtime behind `handle`
+    // instead of one built from `config.runtime`, for host apps already running tokio. Callable
+    // outside a runtime. The manager loop still gets a dedicated OS thread: CoreBluetooth objects
+    // must stay on the thread that created them and the per-peripheral tasks are !Send, so the
+    // thread blocks on `handle` rather than spawning onto its workers. A current thread runtime
+    // only makes progress while its owner is inside block_on, use a multi threaded one.
+    pub fn new_on(handle: Handle, sender_tx: Sender<CentralEvent>, config: Config) -> Result<Self> {
+        Self::start(sender_tx, config, Some(handle))
+    }
+
+    // Shared by new_with_config and new_on, `handle` is the runtime driving the background
+    // central, None builds one from the config.
+    fn start(sender_tx: Sender<CentralEvent>, config: Config, handle: Option<Handle>) -> Result<Self> {
+        super::ensure_authorized()?;
+        let (command_tx, command_rx) = mpsc::channel::<CentralManagerCommand>(256);
+        let (peripheral_command_tx, peripheral_command_rx) =
+            mpsc::channel::<PeripheralRemoteCommand>(256);
+        let last_seen = Arc::new(Mutex::new(HashMap::new()));
+        let (state_tx, state_rx) = watch::channel(CentralState::Unknown);
+        let (events_tx, _) = broadcast::channel::<CentralEvent>(256);
+        let (central_tx, mut central_rx) = mpsc::channel::<CentralEvent>(256);
+        let broadcast_tx = events_tx.clone();
+        let spawner = handle.clone().unwrap_or_else(Handle::current);
+        spawner.spawn(async move {
+            let mut sender_tx = Some(sender_tx);
+            while let Some(event) = central_rx.recv().await {
+                // No subscribers is not an error, the event is simply not broadcast
+                let _ = broadcast_tx.send(event.clone());
+                if let Some(tx) = &sender_tx
+                    && tx.send(event).await.is_err()
+                {
+                    sender_tx = None;
+                }
+            }
+        });
+        run_central_thread(
+            central_tx,
+            command_rx,
+            peripheral_command_rx,
+            last_seen.clone(),
+            state_tx,
+            config.clone(),
+            handle,
+        );
+        Ok(Central {
+            peripherals: HashMap::new(),
+            command_tx,
+            peripheral_command_tx,
+            last_seen,
+            state_rx,
+            events_tx,
+            config,
+        })
+    }
+
+    // Additional receiver of every CentralEvent, independent of the sender given at creation.
+    // NOTE: A receiver falling more than 256 events behind loses the oldest ones and gets
+    // RecvError::Lagged on its next recv.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<CentralEvent> {
+        self.events_tx.subscribe()
+    }
+
+    // Handles of the same peripheral share their cached state, the MTU comes from the background
+    // peripheral which keeps it up to date
+    fn handle(&mut self, id: PeripheralId, mtu: Arc<AtomicUsize>) -> Peripheral {
+        self.peripherals
+            .entry(id.clone())
+            .or_insert_with(|| Peripheral {
+                id,
+                command_tx: self.peripheral_command_tx.clone(),
+                operation_timeout: self.config.operation_timeout,
+                long_writes: self.config.long_writes,
+                mtu,
+                services: Arc::new(Mutex::new(BTreeSet::new())),
+                connection_priority: Arc::new(Mutex::new(ConnectionPriority::default())),
+                last_errors: Arc::new(Mutex::new(HashMap::new())),
+            })
+            .clone()
+    }
+
+    // Known peripherals currently connected through this central, unlike peripherals() which
+    // returns every peripheral discovered
+    pub async fn connected(&mut self) -> Result<Vec<Peripheral>> {
+        let (responder, receiver) = oneshot::channel();
+        self.command_tx
+            .send(CentralManagerCommand::GetPeripherals {
+                connected_only: true,
+                responder,
+            })
+            .await?;
+        let known = receiver.await?;
+        Ok(known
+            .into_iter()
+            .map(|(uuid, mtu)| self.handle(uuid.into(), mtu))
+            .collect())
+    }
+
+    // Every peripheral seen this session with the time of its latest advertisement
+    pub fn discovered_ids(&self) -> Vec<(PeripheralId, Instant)> {
+        self.last_seen
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, seen)| (id.clone(), *seen))
+            .collect()
+    }
+
+    // Disconnect the peripheral and drop everything known about it. Pending operations fail and
+    // existing handles error as the peripheral is unknown until it is discovered again.
+    pub async fn forget(&mut self, id: &PeripheralId) -> Result<()> {
+        self.peripherals.remove(id);
+        self.last_seen.lock().unwrap().remove(id);
+        let (responder, receiver) = oneshot::channel();
+        self.command_tx
+            .send(CentralManagerCommand::ForgetPeripheral {
+                peripheral_uuid: id.uuid(),
+                responder,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    // Connect to `id` and discover its services, the returned handle is ready for reads and
+    // writes. Unlike peripheral() it also finds a device the system remembers from earlier
+    // connections without scanning for it first.
+    pub async fn connect(&mut self, id: &PeripheralId, options: ConnectOptions) -> Result<Peripheral> {
+        let (responder, receiver) = oneshot::channel();
+        self.command_tx
+            .send(CentralManagerCommand::GetPeripheral {
+                peripheral_uuid: id.uuid(),
+                retrieve: true,
+                responder,
+            })
+            .await?;
+        let mtu = receiver.await??;
+        let peripheral = self.handle(id.clone(), mtu);
+        peripheral.connect_with_options(options).await?;
+        peripheral.discover_services().await?;
+        Ok(peripheral)
+    }
+
+    // Connect to a discovered peripheral and stop scanning once connected, a failed connect
+    // leaves the scan running
+    pub async fn connect_and_stop_scan(&mut self, id: &PeripheralId) -> Result<Peripheral> {
+        let peripheral = self.peripheral(id).await?;
+        peripheral.connect().await?;
+        self.stop_scan().await?;
+        Ok(peripheral)
+    }
+
+    // Connect to several peripherals with at most `concurrency` connections pending at once, the
+    // results are in the same order as `ids`
+    pub async fn connect_all(
+        &mut self,
+        ids: &[PeripheralId],
+        concurrency: usize,
+    ) -> Result<Vec<Result<()>>> {
+        let mut handles = Vec::with_capacity(ids.len());
+        for id in ids {
+            handles.push(self.peripheral(id).await);
+        }
+        let results = stream::iter(handles)
+            .map(|handle| async move {
+                match handle {
+                    Ok(peripheral) => peripheral.connect().await,
+                    Err(e) => Err(e),
+                }
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+        Ok(results)
+    }
+
+    // Scan like start_scan but report every advertisement and emit CentralEvent::DeviceLost for a
+    // device that hasn't advertised within `absence_timeout`. Runs until stop_scan.
+    pub async fn monitor_presence(
+        &mut self,
+        filter: ScanFilter,
+        absence_timeout: Duration,
+    ) -> Result<()> {
+        self.command_tx
+            .send(CentralManagerCommand::MonitorPresence {
+                filter,
+                absence_timeout,
+            })
+            .await?;
+        Ok(())
+    }
+
+    // Forget peripherals that haven't advertised within `age`
+    pub fn prune_older_than(&mut self, age: Duration) {
+        let now = Instant::now();
+        self.last_seen
+            .lock()
+            .unwrap()
+            .retain(|_, seen| now.duration_since(*seen) <= age);
+    }
+
+    // Start scanning and invoke `callback` for every discovered device. The callback runs on its
+    // own tokio task, never on the CoreBluetooth dispatch queue, and stops with the scan.
+    pub async fn start_scan_with_callback<F>(
+        &mut self,
+        filter: ScanFilter,
+        mut callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(DiscoveredDevice) + Send + 'static,
+    {
+        let (discovery_tx, mut discovery_rx) = mpsc::channel::<DiscoveredDevice>(256);
+        self.command_tx
+            .send(CentralManagerCommand::StartScanning {
+                filter,
+                discovery_tx: Some(discovery_tx),
+                allow_duplicates: false,
+            })
+            .await?;
+        tokio::spawn(async move {
+            while let Some(device) = discovery_rx.recv().await {
+                callback(device);
+            }
+        });
+        Ok(true)
+    }
+
+    // Scan for `window` and return the device with the strongest smoothed RSSI, the scan is
+    // stopped afterwards
+    pub async fn nearest_device(
+        &mut self,
+        filter: ScanFilter,
+        window: Duration,
+    ) -> Result<Option<DiscoveredDevice>> {
+        let (discovery_tx, mut discovery_rx) = mpsc::channel::<DiscoveredDevice>(256);
+        // Every advertisement is needed for the average, not only the first one per device
+        self.command_tx
+            .send(CentralManagerCommand::StartScanning {
+                filter,
+                discovery_tx: Some(discovery_tx),
+                allow_duplicates: true,
+            })
+            .await?;
+        let mut ranking = RssiRanking::default();
+        let deadline = time::sleep(window);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                device = discovery_rx.recv() => match device {
+                    Some(device) => ranking.record(device),
+                    None => break,
+                },
+            }
+        }
+        self.stop_scan().await?;
+        Ok(ranking.ranked().into_iter().next())
+    }
+
+    // Commission the nearest device advertising `service`: connect, write `credentials` to
+    // `write_char` with response, read `verify_char` back and disconnect. Once connected the
+    // device is disconnected again whatever step fails.
+    pub async fn provision(
+        &mut self,
+        service: Uuid,
+        write_char: Uuid,
+        credentials: &[u8],
+        verify_char: Uuid,
+    ) -> std::result::Result<Provisioned, ProvisionError> {
+        let at = |step: ProvisionStep| move |error: Error| ProvisionError { step, error };
+        let filter = ScanFilter {
+            services: vec![service],
+            ..Default::default()
+        };
+        let device = self
+            .nearest_device(filter, PROVISION_SCAN_WINDOW)
+            .await
+            .map_err(at(ProvisionStep::Scan))?
+            .ok_or_else(|| {
+                at(ProvisionStep::Scan)(Error::from_string(
+                    format!("No device advertising {} found", service),
+                    ErrorType::NotFound,
+                ))
+            })?;
+        let peripheral = self
+            .peripheral(&device.id)
+            .await
+            .map_err(at(ProvisionStep::Connect))?;
+        peripheral.connect().await.map_err(at(ProvisionStep::Connect))?;
+
+        let outcome = async {
+            peripheral
+                .discover_services()
+                .await
+                .map_err(at(ProvisionStep::Discover))?;
+            let find = |uuid: Uuid, step: ProvisionStep| {
+                peripheral
+                    .characteristics_of(service)
+                    .into_iter()
+                    .find(|characteristic| characteristic.uuid == uuid)
+                    .ok_or_else(|| {
+                        at(step)(Error::from_string(
+                            format!("Characteristic {} not found", uuid),
+                            ErrorType::NotFound,
+                        ))
+                    })
+            };
+            let write = find(write_char, ProvisionStep::Write)?;
+            let verify = find(verify_char, ProvisionStep::Verify)?;
+            peripheral
+                .write(&write, credentials, CharacteristicWriteType::WriteWithResponse)
+                .await
+                .map_err(at(ProvisionStep::Write))?;
+            peripheral
+                .read(&verify)
+                .await
+                .map_err(at(ProvisionStep::Verify))
+        }
+        .await;
+
+        let disconnected = peripheral
+            .disconnect()
+            .await
+            .map_err(at(ProvisionStep::Disconnect));
+        let verification = outcome?;
+        disconnected?;
+        Ok(Provisioned {
+            device,
+            verification,
+        })
     }
 }
 
+#[derive(Clone)]
 pub struct Peripheral {
+    id: PeripheralId,
     command_tx: Sender<PeripheralRemoteCommand>,
+    operation_timeout: Duration,
+    long_writes: LongWritePolicy,
+    // Negotiated ATT MTU, refreshed by the background peripheral on every (re)connection
+    mtu: Arc<AtomicUsize>,
+    // GATT tree from the last completed discovery
+    services: Arc<Mutex<BTreeSet<Service>>>,
+    connection_priority: Arc<Mutex<ConnectionPriority>>,
+    // Error of the last failed read, write or subscribe per characteristic, keyed by service and
+    // characteristic UUID, see last_error
+    last_errors: Arc<Mutex<HashMap<(Uuid, Uuid), Error>>>,
+}
+
+impl Peripheral {
+    // A single write, resolving with the number of bytes written
+    async fn write_value(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: CharacteristicWriteType,
+    ) -> Result<usize> {
+        let peripheral_uuid = self.id.uuid();
+        let result = self
+            .send_command(
+                |responder| PeripheralRemoteCommand::WriteCharacteristicValue {
+                    peripheral_uuid,
+                    service_uuid: characteristic.service_uuid,
+                    characteristic_uuid: characteristic.uuid,
+                    data: data.to_vec(),
+                    write_type,
+                    responder,
+                },
+                self.operation_timeout,
+            )
+            .await;
+        self.track((characteristic.service_uuid, characteristic.uuid), result)
+    }
+
+    // Record the outcome of an operation on the (service, characteristic) `key`, a success clears
+    // the error
+    fn track<T>(&self, key: (Uuid, Uuid), result: Result<T>) -> Result<T> {
+        let mut last_errors = self.last_errors.lock().unwrap();
+        match &result {
+            Ok(_) => last_errors.remove(&key),
+            Err(error) => last_errors.insert(key, error.clone()),
+        };
+        result
+    }
+
+    // Subscribe, rolling back when the peripheral never confirms so notifications aren't left
+    // half enabled. The rollback isn't awaited, the subscribe reports the timeout either way.
+    async fn subscribe_characteristic(
+        &self,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        changes_only: bool,
+        notification_tx: Option<NotificationSender>,
+    ) -> Result<()> {
+        let peripheral_uuid = self.id.uuid();
+        let result = self
+            .send_command(
+                |responder| PeripheralRemoteCommand::SubscribeCharacteristic {
+                    peripheral_uuid,
+                    service_uuid,
+                    characteristic_uuid,
+                    changes_only,
+                    notification_tx,
+                    responder,
+                },
+                self.operation_timeout,
+            )
+            .await;
+        if let Err(error) = &result
+            && error.error_type() == ErrorType::Timeout
+        {
+            let (responder, _) = oneshot::channel();
+            let _ = self
+                .command_tx
+                .try_send(PeripheralRemoteCommand::UnsubscribeCharacteristic {
+                    peripheral_uuid,
+                    service_uuid,
+                    characteristic_uuid,
+                    responder,
+                });
+        }
+        self.track((service_uuid, characteristic_uuid), result)
+    }
+
+    // Send a command to the background peripheral and wait for it to resolve the responder
+    async fn send_command<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<Result<T>>) -> PeripheralRemoteCommand,
+        timeout: Duration,
+    ) -> Result<T> {
+        let (responder, receiver) = oneshot::channel();
+        self.command_tx.send(command(responder)).await?;
+        await_response(receiver, timeout).await
+    }
 }
 
 #[async_trait]
@@ -62,7 +568,7 @@ impl PeripheralRemote for Peripheral {
     type PeripheralRemote = Self;
 
     fn id(&self) -> PeripheralId {
-        todo!()
+        self.id.clone()
     }
 
     //fn address(&self) -> BDAddr {
@@ -73,24 +579,116 @@ impl PeripheralRemote for Peripheral {
         todo!()
     }
 
+    async fn names(&self) -> Result<PeripheralNames> {
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::GetNames {
+                peripheral_uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
+    }
+
     fn services(&self) -> BTreeSet<Service> {
-        todo!()
+        self.services.lock().unwrap().clone()
     }
 
     async fn is_connected(&self) -> Result<bool> {
-        todo!()
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::IsConnected {
+                peripheral_uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
     }
 
-    async fn connect(&self) -> Result<()> {
-        todo!()
+    async fn connect_with_options(&self, options: ConnectOptions) -> Result<()> {
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::ConnectDevice {
+                peripheral_uuid,
+                options,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
     }
 
     async fn disconnect(&self) -> Result<()> {
-        todo!()
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::DisconnectDevice {
+                peripheral_uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
     }
 
     async fn discover_services(&self) -> Result<()> {
-        todo!()
+        let peripheral_uuid = self.id.uuid();
+        let services = self
+            .send_command(
+                |responder| PeripheralRemoteCommand::DiscoverServices {
+                    peripheral_uuid,
+                    responder,
+                },
+                self.operation_timeout,
+            )
+            .await?;
+        *self.services.lock().unwrap() = services.into_iter().collect();
+        Ok(())
+    }
+
+    async fn cancel_discovery(&self) -> Result<()> {
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::CancelDiscovery {
+                peripheral_uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
+    }
+
+    async fn cancel_all_operations(&self) -> Result<()> {
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::CancelAllOperations {
+                peripheral_uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
+    }
+
+    async fn refresh(&self) -> Result<Vec<Service>> {
+        let peripheral_uuid = self.id.uuid();
+        self.services.lock().unwrap().clear();
+        let services = self
+            .send_command(
+                |responder| PeripheralRemoteCommand::RefreshServices {
+                    peripheral_uuid,
+                    responder,
+                },
+                self.operation_timeout,
+            )
+            .await?;
+        *self.services.lock().unwrap() = services.iter().cloned().collect();
+        Ok(services)
+    }
+
+    fn recommended_chunk_size(&self, write_type: CharacteristicWriteType) -> usize {
+        chunk_size(self.mtu.load(Ordering::Relaxed), write_type)
     }
 
     async fn write(
@@ -99,100 +697,539 @@ impl PeripheralRemote for Peripheral {
         data: &[u8],
         write_type: CharacteristicWriteType,
     ) -> Result<()> {
-        todo!()
+        self.write_counted(characteristic, data, write_type)
+            .await
+            .map(|_| ())
+    }
+
+    async fn write_counted(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: CharacteristicWriteType,
+    ) -> Result<usize> {
+        if write_type == CharacteristicWriteType::WriteWithResponse
+            && data.len() > MAX_ATTRIBUTE_VALUE_LENGTH
+        {
+            return match self.long_writes {
+                LongWritePolicy::Reject => Err(Error::from_string(
+                    format!(
+                        "Write of {} bytes exceeds the {} byte attribute limit",
+                        data.len(),
+                        MAX_ATTRIBUTE_VALUE_LENGTH
+                    ),
+                    ErrorType::ValueTooLong,
+                )),
+                // The peripheral reassembles the chunks itself, see LongWritePolicy
+                LongWritePolicy::ApplicationFramed => {
+                    let mut written = 0;
+                    for chunk in data.chunks(MAX_ATTRIBUTE_VALUE_LENGTH) {
+                        written += self
+                            .write_value(characteristic, chunk, write_type.clone())
+                            .await?;
+                    }
+                    Ok(written)
+                }
+            };
+        }
+        self.write_value(characteristic, data, write_type).await
     }
 
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
-        todo!()
+        self.read_with_timeout(characteristic, self.operation_timeout)
+            .await
+    }
+
+    async fn read_with_timeout(
+        &self,
+        characteristic: &Characteristic,
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        let peripheral_uuid = self.id.uuid();
+        let result = self
+            .send_command(
+                |responder| PeripheralRemoteCommand::ReadCharacteristicValue {
+                    peripheral_uuid,
+                    service_uuid: characteristic.service_uuid,
+                    characteristic_uuid: characteristic.uuid,
+                    responder,
+                },
+                timeout,
+            )
+            .await;
+        self.track((characteristic.service_uuid, characteristic.uuid), result)
     }
 
     // subscribe to notifications
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        todo!()
+        self.subscribe_characteristic(characteristic.service_uuid, characteristic.uuid, false, None)
+            .await
+    }
+
+    async fn connect_subscribe_with_options(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        options: SubscribeOptions,
+    ) -> Result<BoxStream<'static, Vec<u8>>> {
+        if !self.is_connected().await? {
+            self.connect().await?;
+        }
+        self.discover_services().await?;
+        if !self
+            .characteristics_of(service)
+            .iter()
+            .any(|discovered| discovered.uuid == characteristic)
+        {
+            return Err(Error::from_string(
+                format!("Characteristic {} not found in service {}", characteristic, service),
+                ErrorType::NotFound,
+            ));
+        }
+        let peripheral_uuid = self.id.uuid();
+        let (notification_tx, notification_rx) = notification_channel(options.backpressure);
+        self.subscribe_characteristic(service, characteristic, false, Some(notification_tx))
+            .await?;
+        Ok(Box::pin(NotificationStream {
+            notification_rx,
+            command_tx: self.command_tx.clone(),
+            peripheral_uuid,
+            service_uuid: service,
+            characteristic_uuid: characteristic,
+        }))
+    }
+
+    async fn subscribe_on_change(&self, characteristic: &Characteristic) -> Result<()> {
+        self.subscribe_characteristic(characteristic.service_uuid, characteristic.uuid, true, None)
+            .await
+    }
+
+    fn set_connection_priority(&self, priority: ConnectionPriority) {
+        *self.connection_priority.lock().unwrap() = priority;
+    }
+
+    fn connection_priority(&self) -> ConnectionPriority {
+        *self.connection_priority.lock().unwrap()
+    }
+
+    fn last_error(&self, characteristic: &Characteristic) -> Option<Error> {
+        self.last_errors
+            .lock()
+            .unwrap()
+            .get(&(characteristic.service_uuid, characteristic.uuid))
+            .cloned()
     }
 
     // unsubscribe to notifications
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        todo!()
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::UnsubscribeCharacteristic {
+                peripheral_uuid,
+                service_uuid: characteristic.service_uuid,
+                characteristic_uuid: characteristic.uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
     }
 
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
-        todo!()
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::WriteDescriptorValue {
+                peripheral_uuid,
+                service_uuid: descriptor.service_uuid,
+                characteristic_uuid: descriptor.characteristic_uuid,
+                descriptor_uuid: descriptor.uuid,
+                data: data.to_vec(),
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
     }
 
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
-        todo!()
+        let peripheral_uuid = self.id.uuid();
+        self.send_command(
+            |responder| PeripheralRemoteCommand::ReadDescriptorValue {
+                peripheral_uuid,
+                service_uuid: descriptor.service_uuid,
+                characteristic_uuid: descriptor.characteristic_uuid,
+                descriptor_uuid: descriptor.uuid,
+                responder,
+            },
+            self.operation_timeout,
+        )
+        .await
+    }
+}
+
+// Sending half of a NotificationStream, applying the backpressure strategy of the subscription
+#[derive(Debug)]
+pub struct NotificationSender {
+    notification_tx: Sender<Payload>,
+    // Shared with the stream so the oldest value can be dropped from the sending side
+    notification_rx: Arc<Mutex<mpsc::Receiver<Payload>>>,
+    backpressure: BackpressureStrategy,
+}
+
+impl NotificationSender {
+    // Returns false once the stream has been dropped
+    pub async fn send(&self, value: Payload) -> bool {
+        match self.backpressure {
+            BackpressureStrategy::Block => self.notification_tx.send(value).await.is_ok(),
+            BackpressureStrategy::DropNewest => match self.notification_tx.try_send(value) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Closed(_)) => false,
+            },
+            BackpressureStrategy::DropOldest => {
+                let mut value = value;
+                loop {
+                    match self.notification_tx.try_send(value) {
+                        Ok(()) => return true,
+                        Err(TrySendError::Closed(_)) => return false,
+                        Err(TrySendError::Full(rejected)) => {
+                            let _ = self.notification_rx.lock().unwrap().try_recv();
+                            value = rejected;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn notification_channel(
+    backpressure: BackpressureStrategy,
+) -> (NotificationSender, Arc<Mutex<mpsc::Receiver<Payload>>>) {
+    let (notification_tx, notification_rx) = mpsc::channel::<Payload>(256);
+    let notification_rx = Arc::new(Mutex::new(notification_rx));
+    let sender = NotificationSender {
+        notification_tx,
+        notification_rx: notification_rx.clone(),
+        backpressure,
+    };
+    (sender, notification_rx)
+}
+
+// Notifications of a single subscription, unsubscribes when dropped
+struct NotificationStream {
+    notification_rx: Arc<Mutex<mpsc::Receiver<Payload>>>,
+    command_tx: Sender<PeripheralRemoteCommand>,
+    peripheral_uuid: Uuid,
+    service_uuid: Uuid,
+    characteristic_uuid: Uuid,
+}
+
+impl Stream for NotificationStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.notification_rx
+            .lock()
+            .unwrap()
+            .poll_recv(cx)
+            .map(|value| value.map(payload_into_vec))
+    }
+}
+
+// Ends the background central. A full queue can't take the command, the loop then still ends
+// once it works through the queue and finds the channel closed.
+impl Drop for Central {
+    fn drop(&mut self) {
+        let _ = self.command_tx.try_send(CentralManagerCommand::Shutdown);
+    }
+}
+
+impl Drop for NotificationStream {
+    fn drop(&mut self) {
+        // Drop can't await, nobody waits on the outcome so the responder is discarded
+        let (responder, _) = oneshot::channel();
+        let _ = self
+            .command_tx
+            .try_send(PeripheralRemoteCommand::UnsubscribeCharacteristic {
+                peripheral_uuid: self.peripheral_uuid,
+                service_uuid: self.service_uuid,
+                characteristic_uuid: self.characteristic_uuid,
+                responder,
+            });
+    }
+}
+
+// An MTU below the ATT minimum (e.g. not known yet) is treated as the minimum so the size never
+// underflows
+fn chunk_size(mtu: usize, write_type: CharacteristicWriteType) -> usize {
+    let mtu = mtu.max(DEFAULT_ATT_MTU);
+    match write_type {
+        CharacteristicWriteType::WriteWithoutResponse => mtu - ATT_HEADER_SIZE,
+        CharacteristicWriteType::SignedWriteWithoutResponse => {
+            mtu - ATT_HEADER_SIZE - SIGNATURE_SIZE
+        }
+        CharacteristicWriteType::WriteWithResponse => MAX_ATTRIBUTE_VALUE_LENGTH,
+    }
+}
+
+// Wait for the background peripheral to answer a command, giving up after `duration`
+async fn await_response<T>(
+    receiver: oneshot::Receiver<Result<T>>,
+    duration: Duration,
+) -> Result<T> {
+    match time::timeout(duration, receiver).await {
+        Ok(response) => response?,
+        Err(_) => Err(Error::from_string(
+            "Timeout waiting for peripheral response".to_string(),
+            ErrorType::Timeout,
+        )),
     }
 }
 
+/// Commands sent from a `Peripheral` handle to the background peripheral owning the
+/// `CBPeripheral`. Every command carries a responder which is resolved once CoreBluetooth has
+/// answered through the delegate:
+/// - reads resolve with the value read
+/// - writes resolve with the number of bytes written
+/// - connection, discovery and (un)subscribes resolve with `()` on success
+/// - `IsConnected` and `GetNames` resolve straight away with the current state
+///
+/// `service_uuid` only shows up in the Debug output, the backend looks a characteristic up by
+/// its own UUID.
 #[derive(Debug)]
+#[allow(dead_code)]
 pub enum PeripheralRemoteCommand {
-     ConnectDevice {
+    /// Connect to the peripheral, resolves on didConnectPeripheral
+    ConnectDevice {
         peripheral_uuid: Uuid,
-        responder: oneshot::Sender<Result<bool>>,
+        options: ConnectOptions,
+        responder: oneshot::Sender<Result<()>>,
     },
+    /// Cancel the connection, resolves on didDisconnectPeripheral
     DisconnectDevice {
         peripheral_uuid: Uuid,
-        responder: oneshot::Sender<Result<bool>>,
+        responder: oneshot::Sender<Result<()>>,
     },
+    /// Discover the full GATT tree, resolves once every service, characteristic and descriptor
+    /// has been discovered
     DiscoverServices {
         peripheral_uuid: Uuid,
         responder: oneshot::Sender<Result<Vec<Service>>>,
     },
+    /// Clear the cached GATT tree and discover it again, resolves like `DiscoverServices`
+    RefreshServices {
+        peripheral_uuid: Uuid,
+        responder: oneshot::Sender<Result<Vec<Service>>>,
+    },
+    /// Fail a discovery in progress with a cancellation error, resolves straight away
+    CancelDiscovery {
+        peripheral_uuid: Uuid,
+        responder: oneshot::Sender<Result<()>>,
+    },
+    /// Fail every pending operation with a cancellation error, resolves straight away
+    CancelAllOperations {
+        peripheral_uuid: Uuid,
+        responder: oneshot::Sender<Result<()>>,
+    },
+    /// Read a characteristic value, resolves on didUpdateValueForCharacteristic
     ReadCharacteristicValue {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
-        responder: oneshot::Sender<Result<bool>>,
+        responder: oneshot::Sender<Result<Vec<u8>>>,
     },
+    /// Write a characteristic value, resolves with the number of bytes written on
+    /// didWriteValueForCharacteristic for writes with response and as soon as the write is
+    /// queued for writes without response
     WriteCharacteristicValue {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
         data: Vec<u8>,
         write_type: CharacteristicWriteType,
-        responder: oneshot::Sender<Result<bool>>,
+        responder: oneshot::Sender<Result<usize>>,
     },
+    /// Enable notifications, resolves on didUpdateNotificationStateForCharacteristic. With
+    /// `changes_only` a notification repeating the last forwarded value is dropped.
     SubscribeCharacteristic {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
-        responder: oneshot::Sender<Result<bool>>,
+        changes_only: bool,
+        notification_tx: Option<NotificationSender>,
+        responder: oneshot::Sender<Result<()>>,
     },
+    /// Disable notifications, resolves on didUpdateNotificationStateForCharacteristic
     UnsubscribeCharacteristic {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
-        responder: oneshot::Sender<Result<bool>>,
+        responder: oneshot::Sender<Result<()>>,
     },
     IsConnected {
         peripheral_uuid: Uuid,
         responder: oneshot::Sender<Result<bool>>,
     },
+    GetNames {
+        peripheral_uuid: Uuid,
+        responder: oneshot::Sender<Result<PeripheralNames>>,
+    },
+    /// Read a descriptor value, resolves on didUpdateValueForDescriptor
     ReadDescriptorValue {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
         descriptor_uuid: Uuid,
-        responder: oneshot::Sender<Result<bool>>,
+        responder: oneshot::Sender<Result<Vec<u8>>>,
     },
+    /// Write a descriptor value, resolves on didWriteValueForDescriptor
     WriteDescriptorValue {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
         descriptor_uuid: Uuid,
         data: Vec<u8>,
-        responder: oneshot::Sender<Result<bool>>,
+        responder: oneshot::Sender<Result<()>>,
     },
 }
 
+impl PeripheralRemoteCommand {
+    pub fn peripheral_uuid(&self) -> Uuid {
+        match self {
+            PeripheralRemoteCommand::ConnectDevice { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::DisconnectDevice { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::DiscoverServices { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::RefreshServices { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::CancelDiscovery { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::CancelAllOperations { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::ReadCharacteristicValue { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::WriteCharacteristicValue { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::SubscribeCharacteristic { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::UnsubscribeCharacteristic { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::IsConnected { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::GetNames { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::ReadDescriptorValue { peripheral_uuid, .. }
+            | PeripheralRemoteCommand::WriteDescriptorValue { peripheral_uuid, .. } => {
+                *peripheral_uuid
+            }
+        }
+    }
+
+    // Resolve the responder with `error` without running the command
+    pub fn reject(self, error: Error) {
+        match self {
+            PeripheralRemoteCommand::ConnectDevice { responder, .. }
+            | PeripheralRemoteCommand::DisconnectDevice { responder, .. }
+            | PeripheralRemoteCommand::CancelDiscovery { responder, .. }
+            | PeripheralRemoteCommand::CancelAllOperations { responder, .. }
+            | PeripheralRemoteCommand::SubscribeCharacteristic { responder, .. }
+            | PeripheralRemoteCommand::UnsubscribeCharacteristic { responder, .. }
+            | PeripheralRemoteCommand::WriteDescriptorValue { responder, .. } => {
+                let _ = responder.send(Err(error));
+            }
+            PeripheralRemoteCommand::DiscoverServices { responder, .. }
+            | PeripheralRemoteCommand::RefreshServices { responder, .. } => {
+                let _ = responder.send(Err(error));
+            }
+            PeripheralRemoteCommand::ReadCharacteristicValue { responder, .. }
+            | PeripheralRemoteCommand::ReadDescriptorValue { responder, .. } => {
+                let _ = responder.send(Err(error));
+            }
+            PeripheralRemoteCommand::WriteCharacteristicValue { responder, .. } => {
+                let _ = responder.send(Err(error));
+            }
+            PeripheralRemoteCommand::IsConnected { responder, .. } => {
+                let _ = responder.send(Err(error));
+            }
+            PeripheralRemoteCommand::GetNames { responder, .. } => {
+                let _ = responder.send(Err(error));
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum CentralManagerCommand {
-    GetAdapterState {
-        responder: oneshot::Sender<Result<bool>>,
-    },
     StartScanning {
         filter: ScanFilter,
+        // Additional per-scan receiver of discoveries, dropped when the scan stops
+        discovery_tx: Option<Sender<DiscoveredDevice>>,
+        // Report every advertisement instead of only the first one per device
+        allow_duplicates: bool,
     },
     StopScanning,
+    // Stop scanning and end the background central, sent when the Central is dropped
+    Shutdown,
+    MonitorPresence {
+        filter: ScanFilter,
+        absence_timeout: Duration,
+    },
+    ForgetPeripheral {
+        peripheral_uuid: Uuid,
+        responder: oneshot::Sender<Result<()>>,
+    },
+    // Resolves with the MTU shared with the background peripheral, UnknownPeripheral if it
+    // hasn't been discovered (or retrieved)
+    GetPeripheral {
+        peripheral_uuid: Uuid,
+        // Fall back to the peripherals the system remembers when this central hasn't seen it
+        retrieve: bool,
+        responder: oneshot::Sender<Result<Arc<AtomicUsize>>>,
+    },
+    GetPeripherals {
+        // Only peripherals currently connected through this central
+        connected_only: bool,
+        responder: oneshot::Sender<Vec<(Uuid, Arc<AtomicUsize>)>>,
+    },
    }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The second Central must get a manager thread of its own rather than a dead channel
+    #[tokio::test]
+    async fn centrals_constructed_one_after_the_other_both_answer() {
+        let (sender_tx, _sender_rx) = mpsc::channel(16);
+        let Ok(mut first) = Central::new(sender_tx.clone()).await else {
+            // Bluetooth access refused on this machine
+            return;
+        };
+        assert!(first.peripherals().await.is_ok());
+        drop(first);
+
+        let mut second = Central::new(sender_tx).await.unwrap();
+        assert!(second.peripherals().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn dropping_a_scanning_central_stops_the_scan() {
+        let (sender_tx, _sender_rx) = mpsc::channel(16);
+        let Ok(mut central) = Central::new(sender_tx).await else {
+            // Bluetooth access refused on this machine
+            return;
+        };
+        let mut events = central.subscribe_events();
+        central.start_scan(ScanFilter::default()).await.unwrap();
+        drop(central);
+        let stopped = time::timeout(Duration::from_secs(5), async {
+            loop {
+                match events.recv().await {
+                    Ok(CentralEvent::ScanStopped) => return true,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return false,
+                }
+            }
+        })
+        .await;
+        assert_eq!(stopped, Ok(true));
+    }
+
+    #[test]
+    fn chunk_size_of_an_unknown_mtu_does_not_underflow() {
+        let signed = CharacteristicWriteType::SignedWriteWithoutResponse;
+        assert_eq!(chunk_size(0, signed.clone()), DEFAULT_ATT_MTU - ATT_HEADER_SIZE - SIGNATURE_SIZE);
+        assert_eq!(chunk_size(185, signed), 185 - ATT_HEADER_SIZE - SIGNATURE_SIZE);
+        assert_eq!(chunk_size(0, CharacteristicWriteType::WriteWithoutResponse), 20);
+    }
+}
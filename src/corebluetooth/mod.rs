@@ -1,3 +1,105 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::{
+    Error, ErrorType, Result,
+    api::{adapter::Authorization, central_event::CentralState},
+    corebluetooth::objc_bindings::adapter_cb,
+};
+
 mod objc_bindings;
-mod peripheral_manager;
-mod central_manager;
+pub mod peripheral_manager;
+pub mod central_manager;
+pub mod adapter;
+
+// Fail manager creation straight away when the user or a device policy refused Bluetooth,
+// the manager would only ever report Unauthorized. NotDetermined passes, creating the manager
+// is what shows the prompt.
+fn ensure_authorized() -> Result<()> {
+    check_authorization(adapter_cb::authorization())
+}
+
+fn check_authorization(authorization: Authorization) -> Result<()> {
+    match authorization {
+        Authorization::Denied | Authorization::Restricted => Err(unauthorized()),
+        Authorization::NotDetermined | Authorization::Allowed => Ok(()),
+    }
+}
+
+fn unauthorized() -> Error {
+    Error::from_string(
+        "Bluetooth access is not authorized".to_string(),
+        ErrorType::Unauthorized,
+    )
+}
+
+// Resolve once the manager state reaches PoweredOn, failing early on states the app can't wait
+// out. Both managers publish their CBManagerState through a watch channel.
+async fn wait_until_powered_on(
+    mut state_rx: watch::Receiver<CentralState>,
+    timeout: Duration,
+) -> Result<()> {
+    let settled = state_rx.wait_for(|state| {
+        matches!(
+            state,
+            CentralState::PoweredOn | CentralState::Unsupported | CentralState::Unauthorized
+        )
+    });
+    let state = match time::timeout(timeout, settled).await {
+        Ok(Ok(state)) => state.clone(),
+        Ok(Err(_)) => {
+            return Err(Error::from_string(
+                "Manager state channel closed".to_string(),
+                ErrorType::ChannelError,
+            ));
+        }
+        Err(_) => {
+            return Err(Error::from_string(
+                "Timeout waiting for the adapter to power on".to_string(),
+                ErrorType::Timeout,
+            ));
+        }
+    };
+    match state {
+        CentralState::PoweredOn => Ok(()),
+        CentralState::Unsupported => Err(Error::from_string(
+            "Bluetooth LE is not supported on this device".to_string(),
+            ErrorType::NotSupported,
+        )),
+        _ => Err(unauthorized()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denied_or_restricted_access_is_unauthorized() {
+        for authorization in [Authorization::Denied, Authorization::Restricted] {
+            let error = check_authorization(authorization).unwrap_err();
+            assert_eq!(error.error_type(), ErrorType::Unauthorized);
+        }
+        assert!(check_authorization(Authorization::NotDetermined).is_ok());
+        assert!(check_authorization(Authorization::Allowed).is_ok());
+    }
+
+    #[tokio::test]
+    async fn unauthorized_state_fails_the_wait_straight_away() {
+        let (_state_tx, state_rx) = watch::channel(CentralState::Unauthorized);
+        let error = wait_until_powered_on(state_rx, Duration::from_secs(60))
+            .await
+            .unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::Unauthorized);
+    }
+
+    #[tokio::test]
+    async fn wait_resolves_once_powered_on() {
+        let (state_tx, state_rx) = watch::channel(CentralState::Unknown);
+        let waiting = tokio::spawn(wait_until_powered_on(state_rx, Duration::from_secs(60)));
+        state_tx.send_replace(CentralState::PoweredOn);
+        assert!(waiting.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,68 @@
+use super::central_manager_delegate_cb::{CentralManagerDelegate, CentralManagerDelegateEvent};
+use super::{build_runtime, mac_utils_cb, queue_label};
+use crate::api::adapter::Authorization;
+use crate::api::central_event::CentralState;
+use crate::api::config::RuntimeFlavor;
+use objc2::{AnyThread, msg_send};
+use objc2::{rc::Retained, runtime::AnyObject};
+use objc2_core_bluetooth::{CBCentralManager, CBManager, CBManagerAuthorization};
+use std::thread;
+use tokio::sync::{mpsc, oneshot, watch};
+
+// Watch the adapter state with a central manager of its own that never scans or connects. The
+// thread ends once `shutdown_rx` resolves, dropping `state_tx` ends every state stream.
+pub fn run_adapter_thread(
+    state_tx: watch::Sender<CentralState>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+    queue: &str,
+) {
+    let label = queue_label(queue);
+    thread::spawn(move || {
+        let runtime = match build_runtime(&RuntimeFlavor::CurrentThread) {
+            Ok(runtime) => runtime,
+            Err(_) => {
+                log::error!("Failed to create runtime");
+                return;
+            }
+        };
+        let (delegate_tx, mut delegate_rx) = mpsc::channel::<CentralManagerDelegateEvent>(16);
+        let delegate: Retained<CentralManagerDelegate> = CentralManagerDelegate::new(delegate_tx);
+        let queue = unsafe {
+            mac_utils_cb::dispatch_queue_create(label.as_ptr(), mac_utils_cb::DISPATCH_QUEUE_SERIAL)
+        };
+        let queue: *mut AnyObject = queue.cast();
+        // Kept alive for as long as the thread runs, dropping it stops the state updates
+        let _manager: Retained<CBCentralManager> = unsafe {
+            msg_send![CBCentralManager::alloc(), initWithDelegate: &*delegate, queue: queue]
+        };
+        runtime.block_on(async move {
+            loop {
+                tokio::select! {
+                    event = delegate_rx.recv() => match event {
+                        Some(CentralManagerDelegateEvent::StateUpdate { state }) => {
+                            state_tx.send_replace(state);
+                        }
+                        Some(_) => {}
+                        None => break,
+                    },
+                    // Sent or dropped alike, the Adapter is gone
+                    _ = &mut shutdown_rx => break,
+                }
+            }
+        });
+    });
+}
+
+// Authorization of this app, readable without creating a manager
+pub fn authorization() -> Authorization {
+    match unsafe { CBManager::authorization_class() } {
+        CBManagerAuthorization::NotDetermined => Authorization::NotDetermined,
+        CBManagerAuthorization::Restricted => Authorization::Restricted,
+        CBManagerAuthorization::Denied => Authorization::Denied,
+        CBManagerAuthorization::AllowedAlways => Authorization::Allowed,
+        _ => {
+            log::warn!("Unexpected CBManagerAuthorization value, treating as NotDetermined");
+            Authorization::NotDetermined
+        }
+    }
+}
@@ -1,58 +1,160 @@
+use super::{build_runtime, queue_label};
 use super::mac_utils_cb;
-use crate::corebluetooth::central_manager::{CentralManagerCommand, Peripheral};
+use super::peripheral_cb::{Peripheral, PeripheralMessage};
+use super::mac_extensions_cb::uuid_to_cbuuid;
+use crate::api::central::{DiscoveredDevice, PeripheralId, ScanFilter};
+use crate::api::central_event::{CentralState, DisconnectReason};
+use crate::api::characteristic::DEFAULT_ATT_MTU;
+use crate::api::config::Config;
+use crate::corebluetooth::central_manager::{CentralManagerCommand, PeripheralRemoteCommand};
+use crate::{Error, ErrorType};
 use crate::corebluetooth::objc_bindings::central_manager_delegate_cb::{
     CentralManagerDelegate, CentralManagerDelegateEvent,
 };
 use objc2::{AnyThread, msg_send};
 use objc2::{rc::Retained, runtime::AnyObject};
-use objc2_core_bluetooth::CBCentralManager;
-use std::collections::HashMap;
-use std::ffi::CString;
-use std::sync::OnceLock;
+use objc2_core_bluetooth::{
+    CBCentralManager, CBCentralManagerScanOptionAllowDuplicatesKey, CBPeripheral,
+    CBPeripheralState, CBUUID,
+};
+use futures::future;
+use objc2_foundation::{NSArray, NSDictionary, NSNumber, NSString, NSUUID};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, atomic::AtomicUsize};
+use std::time::{Duration, Instant};
 use std::thread;
-use tokio::runtime;
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::mpsc::{self, Receiver, Sender, error::TrySendError};
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+use tokio::task::{self, LocalSet};
+use tokio::time::{self, Interval, MissedTickBehavior};
 use uuid::Uuid;
 
 use crate::api::central_event::CentralEvent;
 
-static CENTRAL_THREAD: OnceLock<()> = OnceLock::new();
-
-// Handle Peripheral Manager and all communication in a separate thread
-pub fn run_central_thread(sender: Sender<CentralEvent>, listener: Receiver<CentralManagerCommand>) {
-    CENTRAL_THREAD.get_or_init(|| {
-        thread::spawn(move || {
-            let runtime = runtime::Builder::new_current_thread().enable_time().build();
-            if runtime.is_err() {
-                log::error!("Failed to create runtime");
-                return;
-            }
-            runtime.unwrap().block_on(async move {
-                let mut central_manager = CentralManager::new(sender, listener);
-                loop {
-                    central_manager.handle_event().await;
-                }
-            })
+// Handle Peripheral Manager and all communication in a separate thread. The thread drives the
+// manager loop on `handle` when given, otherwise on a runtime of its own built from the config.
+// Every Central gets its own thread, which ends once that Central is dropped.
+pub fn run_central_thread(
+    sender: Sender<CentralEvent>,
+    listener: Receiver<CentralManagerCommand>,
+    peripheral_listener: Receiver<PeripheralRemoteCommand>,
+    last_seen: Arc<Mutex<HashMap<PeripheralId, Instant>>>,
+    state_tx: watch::Sender<CentralState>,
+    config: Config,
+    handle: Option<Handle>,
+) {
+    thread::spawn(move || {
+        // Peripherals hold on to CoreBluetooth objects which can't leave this thread, their
+        // tasks are spawned locally
+        let local = LocalSet::new();
+        let runtime_config = config.runtime.clone();
+        let manager_loop = local.run_until(async move {
+            let mut central_manager = CentralManager::new(sender, listener, peripheral_listener, last_seen, state_tx, config);
+            while central_manager.handle_event().await {}
         });
+        match handle {
+            Some(handle) => handle.block_on(manager_loop),
+            None => match build_runtime(&runtime_config) {
+                Ok(runtime) => runtime.block_on(manager_loop),
+                Err(_) => log::error!("Failed to create runtime"),
+            },
+        }
     });
 }
 
+// Content of the last advertisement of a device, see ScanFilter::emit_on_change_only
+#[derive(Default)]
+struct AdvertisedContent {
+    // Name and services of the discovery itself
+    discovered: Option<(Option<String>, Vec<Uuid>)>,
+    manufacturer_data: Option<(u16, Vec<u8>)>,
+    service_data: Option<HashMap<Uuid, Vec<u8>>>,
+    advertised_services: Option<Vec<Uuid>>,
+    // When an advertisement of the device was last reported, see
+    // ScanFilter::max_events_per_device_per_sec
+    last_emitted: Option<Instant>,
+    // Whether the advertisement being received is dropped by the rate limit
+    throttled: bool,
+}
+
+impl AdvertisedContent {
+    // Start of a new advertisement, decides whether it fits in the rate limit
+    fn throttle(&mut self, max_per_sec: Option<u32>) -> bool {
+        let now = Instant::now();
+        self.throttled = is_throttled(max_per_sec, self.last_emitted, now);
+        if !self.throttled {
+            self.last_emitted = Some(now);
+        }
+        self.throttled
+    }
+}
+
+struct Presence {
+    absence_timeout: Duration,
+    sweep: Interval,
+    // Devices seen since monitoring started and not lost since
+    present: HashSet<Uuid>,
+}
+
+// The central manager's end of a peripheral running on its own task
+struct PeripheralHandle {
+    message_tx: Sender<PeripheralMessage>,
+    mtu: Arc<AtomicUsize>,
+    // Same object the task drives, only used to read the connection state
+    peripheral: Retained<CBPeripheral>,
+}
+
+impl PeripheralHandle {
+    // Never waits so a busy peripheral can't hold up the central manager or other peripherals
+    fn send(&self, message: PeripheralMessage) -> std::result::Result<(), PeripheralMessage> {
+        self.message_tx.try_send(message).map_err(|e| match e {
+            TrySendError::Full(message) | TrySendError::Closed(message) => message,
+        })
+    }
+
+    // Connection changes must never be dropped, the peripheral's state would go stale. Waits for
+    // room, which the peripheral's task makes as it runs on the same LocalSet.
+    async fn send_lifecycle(&self, message: PeripheralMessage) -> bool {
+        self.message_tx.send(message).await.is_ok()
+    }
+}
+
 struct CentralManager {
     manager: Retained<CBCentralManager>,
-    delegate: Retained<CentralManagerDelegate>,
-    peripherals: HashMap<Uuid, Peripheral>,
+    // Only kept alive, CBCentralManager holds a weak reference to its delegate
+    _delegate: Retained<CentralManagerDelegate>,
+    peripherals: HashMap<Uuid, PeripheralHandle>,
     manager_command_rx: Receiver<CentralManagerCommand>,
+    peripheral_command_rx: Receiver<PeripheralRemoteCommand>,
     corebluetooth_delegate_rx: Receiver<CentralManagerDelegateEvent>,
     central_tx: Sender<CentralEvent>,
+    discovery_tx: Option<Sender<DiscoveredDevice>>,
+    // Filter of the running scan, reapplied to every discovery
+    scan_filter: ScanFilter,
+    // Set while monitoring presence, cleared when the scan stops
+    presence: Option<Presence>,
+    // Last advertisement of every device seen by the running scan
+    advertised: HashMap<Uuid, AdvertisedContent>,
+    last_seen: Arc<Mutex<HashMap<PeripheralId, Instant>>>,
+    state_tx: watch::Sender<CentralState>,
+    config: Config,
 }
 
 impl CentralManager {
-    fn new(central_tx: Sender<CentralEvent>, manager_rx: Receiver<CentralManagerCommand>) -> Self {
+    fn new(
+        central_tx: Sender<CentralEvent>,
+        manager_rx: Receiver<CentralManagerCommand>,
+        peripheral_rx: Receiver<PeripheralRemoteCommand>,
+        last_seen: Arc<Mutex<HashMap<PeripheralId, Instant>>>,
+        state_tx: watch::Sender<CentralState>,
+        config: Config,
+    ) -> Self {
         let (delegate_tx, delegate_rx) = mpsc::channel::<CentralManagerDelegateEvent>(256);
 
         let delegate: Retained<CentralManagerDelegate> = CentralManagerDelegate::new(delegate_tx);
 
-        let label = CString::new("CBqueue").unwrap();
+        let label = queue_label(&config.central_queue);
         let queue = unsafe {
             mac_utils_cb::dispatch_queue_create(label.as_ptr(), mac_utils_cb::DISPATCH_QUEUE_SERIAL)
         };
@@ -64,30 +166,419 @@ impl CentralManager {
 
         Self {
             manager,
-            delegate,
+            _delegate: delegate,
             peripherals: HashMap::new(),
             manager_command_rx: manager_rx,
+            peripheral_command_rx: peripheral_rx,
             corebluetooth_delegate_rx: delegate_rx,
             central_tx,
+            discovery_tx: None,
+            scan_filter: ScanFilter::default(),
+            presence: None,
+            advertised: HashMap::new(),
+            last_seen,
+            state_tx,
+            config,
         }
     }
 
-    async fn handle_event(&mut self) {
+    // False once the Central is dropped, which ends the manager loop
+    async fn handle_event(&mut self) -> bool {
         tokio::select! {
             // Match events from above
-            Some(manager_command) = self.manager_command_rx.recv() => {
+            manager_command = self.manager_command_rx.recv() => {
+                let Some(manager_command) = manager_command else {
+                    return false;
+                };
                 match manager_command {
-                    CentralManagerCommand::GetAdapterState { responder } => todo!(),
-                    CentralManagerCommand::StartScanning { filter } => todo!(),
-                    CentralManagerCommand::StopScanning => todo!(),
+                    CentralManagerCommand::StartScanning { filter, discovery_tx, allow_duplicates } => self.start_scan(filter, discovery_tx, allow_duplicates),
+                    CentralManagerCommand::MonitorPresence { filter, absence_timeout } => self.monitor_presence(filter, absence_timeout),
+                    CentralManagerCommand::StopScanning => self.stop_scan().await,
+                    // Stop the radio before the loop ends and the CBCentralManager is released
+                    CentralManagerCommand::Shutdown => {
+                        self.stop_scan().await;
+                        return false;
+                    }
+                    CentralManagerCommand::ForgetPeripheral { peripheral_uuid, responder } => {
+                        self.forget_peripheral(peripheral_uuid);
+                        let _ = responder.send(Ok(()));
+                    }
+                    CentralManagerCommand::GetPeripheral { peripheral_uuid, retrieve, responder } => {
+                        if retrieve && !self.peripherals.contains_key(&peripheral_uuid) {
+                            self.retrieve_peripheral(peripheral_uuid);
+                        }
+                        let mtu = match self.peripherals.get(&peripheral_uuid) {
+                            Some(peripheral) => Ok(peripheral.mtu.clone()),
+                            None => Err(unknown_peripheral(peripheral_uuid)),
+                        };
+                        let _ = responder.send(mtu);
+                    }
+                    CentralManagerCommand::GetPeripherals { connected_only, responder } => {
+                        let known = self
+                            .peripherals
+                            .iter()
+                            .filter(|(_, peripheral)| {
+                                !connected_only
+                                    || unsafe { peripheral.peripheral.state() } == CBPeripheralState::Connected
+                            })
+                            .map(|(uuid, peripheral)| (*uuid, peripheral.mtu.clone()))
+                            .collect();
+                        let _ = responder.send(known);
+                    }
+                }
+            }
+
+            // Route peripheral commands to the peripheral they target
+            Some(peripheral_command) = self.peripheral_command_rx.recv() => {
+                let peripheral_uuid = peripheral_command.peripheral_uuid();
+                match self.peripherals.get(&peripheral_uuid) {
+                    Some(peripheral) => {
+                        if let Err(PeripheralMessage::Command(command)) = peripheral.send(PeripheralMessage::Command(peripheral_command)) {
+                            command.reject(Error::from_string(
+                                format!("Peripheral {} is not keeping up with commands", peripheral_uuid),
+                                ErrorType::ChannelError,
+                            ));
+                        }
+                    }
+                    None => peripheral_command.reject(unknown_peripheral(peripheral_uuid)),
                 }
             }
 
             // Match events from Corebluetooth delegate
             Some(delegate_event) = self.corebluetooth_delegate_rx.recv() => {
                 match delegate_event {
+                    CentralManagerDelegateEvent::DeviceDiscovered { server, peripheral, services, name, rssi } => {
+                        if !self.scan_filter.matches(&server.into(), &services) {
+                            return true;
+                        }
+                        self.add_peripheral(server, peripheral);
+                        if let Some(presence) = self.presence.as_mut() {
+                            presence.present.insert(server);
+                        }
+                        self.notify_peripheral(server, PeripheralMessage::Advertised(name.clone()));
+                        self.last_seen
+                            .lock()
+                            .unwrap()
+                            .insert(server.into(), Instant::now());
+                        let content = self.advertised.entry(server).or_default();
+                        if content.throttle(self.scan_filter.max_events_per_device_per_sec) {
+                            return true;
+                        }
+                        let discovered = Some((name.clone(), services));
+                        if !report_advertised(&mut content.discovered, discovered, self.scan_filter.emit_on_change_only) {
+                            return true;
+                        }
+                        let name = name.unwrap_or_else(|| String::from("Unknown"));
+                        if let Some(discovery_tx) = &self.discovery_tx {
+                            let device = DiscoveredDevice {
+                                id: server.into(),
+                                name: name.clone(),
+                                rssi,
+                            };
+                            if discovery_tx.send(device).await.is_err() {
+                                self.discovery_tx = None;
+                            }
+                        }
+                        self.send_event(CentralEvent::DeviceDiscovered { server, name, rssi }).await;
+                    }
+                    // Tracked peripherals report their own connection changes as only they know
+                    // whether it is a reconnect or why the link went down
+                    CentralManagerDelegateEvent::DeviceConnected { server } => {
+                        if !self.peripherals.contains_key(&server) {
+                            self.send_event(CentralEvent::DeviceConnected { server, reconnect: false }).await;
+                        }
+                        self.notify_lifecycle(server, PeripheralMessage::Connected).await;
+                    }
+                    CentralManagerDelegateEvent::DeviceDisconnected { server } => {
+                        // Only a forgotten peripheral can still disconnect once untracked
+                        if !self.peripherals.contains_key(&server) {
+                            let reason = DisconnectReason::Requested;
+                            self.send_event(CentralEvent::DeviceDisconnected { server, reason }).await;
+                        }
+                        self.notify_lifecycle(server, PeripheralMessage::Disconnected).await;
+                    }
+                    CentralManagerDelegateEvent::StateUpdate { state } => {
+                        self.state_tx.send_replace(state.clone());
+                        self.send_event(CentralEvent::StateUpdate { state }).await;
+                    }
+                    // Only devices that passed the scan filter are tracked
+                    CentralManagerDelegateEvent::ManufacturerDataAdvertisement { server, manufacturer_id, manufacturer_data } => {
+                        if !self.peripherals.contains_key(&server) {
+                            return true;
+                        }
+                        let data = Some((manufacturer_id, manufacturer_data.clone()));
+                        if self.record_advertised(server, |content| &mut content.manufacturer_data, data) {
+                            self.send_event(CentralEvent::ManufacturerDataAdvertisement { server, manufacturer_id, manufacturer_data }).await;
+                        }
+                    }
+                    CentralManagerDelegateEvent::ServiceDataAdvertisement { server, service_data } => {
+                        if !self.peripherals.contains_key(&server) {
+                            return true;
+                        }
+                        let data = Some(service_data.clone());
+                        if self.record_advertised(server, |content| &mut content.service_data, data) {
+                            self.send_event(CentralEvent::ServiceDataAdvertisement { server, service_data: service_data.into() }).await;
+                        }
+                    }
+                    CentralManagerDelegateEvent::ServicesAdvertisement { server, services } => {
+                        if !self.peripherals.contains_key(&server) {
+                            return true;
+                        }
+                        let data = Some(services.clone());
+                        if self.record_advertised(server, |content| &mut content.advertised_services, data) {
+                            self.send_event(CentralEvent::ServicesAdvertisement { server, services }).await;
+                        }
+                    }
                 }
             }
+
+            // Report monitored devices that went quiet
+            _ = presence_sweep(&mut self.presence) => self.sweep_presence().await,
         };
+        true
+    }
+
+    // Remember one part of a device's advertisement, returns whether it should be reported
+    fn record_advertised<T: PartialEq>(
+        &mut self,
+        server: Uuid,
+        field: impl FnOnce(&mut AdvertisedContent) -> &mut Option<T>,
+        value: Option<T>,
+    ) -> bool {
+        let content = self.advertised.entry(server).or_default();
+        if content.throttled {
+            return false;
+        }
+        report_advertised(field(content), value, self.scan_filter.emit_on_change_only)
+    }
+
+    // Look up a peripheral this scan hasn't seen but the system still knows, e.g. one connected
+    // in an earlier run, and track it as if it had been discovered
+    fn retrieve_peripheral(&mut self, server: Uuid) {
+        let identifiers = NSArray::from_retained_slice(&[NSUUID::from_bytes(*server.as_bytes())]);
+        let retrieved = unsafe { self.manager.retrievePeripheralsWithIdentifiers(&identifiers) };
+        if let Some(peripheral) = retrieved.firstObject() {
+            self.add_peripheral(server, peripheral);
+        }
+    }
+
+    // CoreBluetooth keeps handing out the same CBPeripheral for an identifier, so the first
+    // discovery creates the background peripheral and later ones are ignored.
+    fn add_peripheral(&mut self, server: Uuid, peripheral: Retained<CBPeripheral>) {
+        if self.peripherals.contains_key(&server) {
+            return;
+        }
+        let mtu = Arc::new(AtomicUsize::new(DEFAULT_ATT_MTU));
+        let handle = peripheral.clone();
+        let peripheral = Peripheral::new(
+            peripheral,
+            self.manager.clone(),
+            self.central_tx.clone(),
+            mtu.clone(),
+            self.config.supervision.clone(),
+        );
+        let (message_tx, message_rx) = mpsc::channel::<PeripheralMessage>(256);
+        task::spawn_local(peripheral.run(message_rx));
+        self.peripherals.insert(
+            server,
+            PeripheralHandle {
+                message_tx,
+                mtu,
+                peripheral: handle,
+            },
+        );
+    }
+
+    fn notify_peripheral(&self, server: Uuid, message: PeripheralMessage) {
+        if let Some(peripheral) = self.peripherals.get(&server)
+            && peripheral.send(message).is_err()
+        {
+            log::error!("Peripheral {} dropped a message, its queue is full", server);
+        }
+    }
+
+    async fn notify_lifecycle(&self, server: Uuid, message: PeripheralMessage) {
+        if let Some(peripheral) = self.peripherals.get(&server)
+            && !peripheral.send_lifecycle(message).await
+        {
+            log::error!("Peripheral {} is gone, its connection change was lost", server);
+        }
+    }
+
+    // Dropping the handle ends the peripheral's task once it has worked through the messages
+    // already queued, which cancels the connection and releases the CBPeripheral and its
+    // delegate. Commands for it are rejected as unknown from here on.
+    fn forget_peripheral(&mut self, peripheral_uuid: Uuid) {
+        self.peripherals.remove(&peripheral_uuid);
+        self.advertised.remove(&peripheral_uuid);
+        self.last_seen
+            .lock()
+            .unwrap()
+            .remove(&peripheral_uuid.into());
+    }
+
+    // With `allow_duplicates` every advertisement is reported instead of only the first one per
+    // device, which presence monitoring needs to notice a device going quiet.
+    fn start_scan(
+        &mut self,
+        filter: ScanFilter,
+        discovery_tx: Option<Sender<DiscoveredDevice>>,
+        allow_duplicates: bool,
+    ) {
+        let services: Option<Retained<NSArray<CBUUID>>> = if filter.services.is_empty()
+            || filter.match_overflow
+        {
+            None
+        } else {
+            Some(NSArray::from_retained_slice(
+                &filter
+                    .services
+                    .iter()
+                    .map(|uuid| uuid_to_cbuuid(*uuid))
+                    .collect::<Vec<_>>(),
+            ))
+        };
+        self.discovery_tx = discovery_tx;
+        self.scan_filter = filter;
+        self.advertised.clear();
+        let options = allow_duplicates.then(|| {
+            let allow = NSNumber::new_bool(true);
+            NSDictionary::<NSString, AnyObject>::from_slices(
+                &[unsafe { CBCentralManagerScanOptionAllowDuplicatesKey }],
+                &[allow.as_ref()],
+            )
+        });
+        unsafe {
+            self.manager
+                .scanForPeripheralsWithServices_options(services.as_deref(), options.as_deref())
+        };
+    }
+
+    fn monitor_presence(&mut self, filter: ScanFilter, absence_timeout: Duration) {
+        // Sweep a few times per timeout so a lost device is reported close to the timeout
+        let mut sweep = time::interval((absence_timeout / 4).max(Duration::from_millis(100)));
+        sweep.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        self.presence = Some(Presence {
+            absence_timeout,
+            sweep,
+            present: HashSet::new(),
+        });
+        self.start_scan(filter, None, true);
+    }
+
+    async fn sweep_presence(&mut self) {
+        let Some(presence) = self.presence.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        let lost: Vec<Uuid> = {
+            let last_seen = self.last_seen.lock().unwrap();
+            presence
+                .present
+                .iter()
+                .filter(|uuid| match last_seen.get(&PeripheralId::from(**uuid)) {
+                    Some(seen) => now.duration_since(*seen) > presence.absence_timeout,
+                    None => true,
+                })
+                .copied()
+                .collect()
+        };
+        for server in lost.iter() {
+            presence.present.remove(server);
+        }
+        for server in lost {
+            self.send_event(CentralEvent::DeviceLost { server }).await;
+        }
+    }
+
+    async fn stop_scan(&mut self) {
+        // Dropping the sender ends any callback task attached to this scan
+        self.discovery_tx = None;
+        self.presence = None;
+        unsafe { self.manager.stopScan() };
+        self.send_event(CentralEvent::ScanStopped).await;
+    }
+
+    async fn send_event(&self, event: CentralEvent) {
+        if let Err(e) = self.central_tx.send(event).await {
+            log::error!("Error sending central event: {}", e);
+        }
+    }
+}
+
+async fn presence_sweep(presence: &mut Option<Presence>) {
+    match presence {
+        Some(presence) => {
+            presence.sweep.tick().await;
+        }
+        None => future::pending::<()>().await,
+    }
+}
+
+// Whether an advertisement at `now` comes sooner than 1 / max_per_sec seconds after the last
+// reported one of the device. A limit of 0 is treated as 1.
+fn is_throttled(max_per_sec: Option<u32>, last_emitted: Option<Instant>, now: Instant) -> bool {
+    match (max_per_sec, last_emitted) {
+        (Some(max), Some(last)) => now.duration_since(last) < Duration::from_secs(1) / max.max(1),
+        _ => false,
+    }
+}
+
+// Replace the last seen part of an advertisement, returns whether the new one is reported. The
+// first one of a device always counts as a change.
+fn report_advertised<T: PartialEq>(last: &mut Option<T>, value: Option<T>, emit_on_change_only: bool) -> bool {
+    let changed = *last != value;
+    *last = value;
+    changed || !emit_on_change_only
+}
+
+fn unknown_peripheral(uuid: Uuid) -> Error {
+    Error::from_string(
+        format!("No peripheral known with UUID {}", uuid),
+        ErrorType::UnknownPeripheral,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_advertisement_is_only_reported_without_emit_on_change() {
+        let mut last = None;
+        assert!(report_advertised(&mut last, Some(vec![1u8]), true));
+        assert!(!report_advertised(&mut last, Some(vec![1u8]), true));
+        assert!(report_advertised(&mut last, Some(vec![1u8]), false));
+        assert_eq!(last, Some(vec![1u8]));
+    }
+
+    #[test]
+    fn changed_advertisement_is_reported() {
+        let mut last = Some((Some("sensor".to_string()), vec![Uuid::nil()]));
+        let renamed = Some((Some("sensor 2".to_string()), vec![Uuid::nil()]));
+        assert!(report_advertised(&mut last, renamed.clone(), true));
+        assert_eq!(last, renamed);
+        // A nameless first discovery is a change too
+        let mut last = None;
+        assert!(report_advertised(&mut last, Some((None::<String>, Vec::<Uuid>::new())), true));
+    }
+
+    #[test]
+    fn advertisement_within_the_interval_is_throttled() {
+        let last = Instant::now();
+        // 4 per second leaves 250ms between reported advertisements
+        assert!(is_throttled(Some(4), Some(last), last));
+        assert!(is_throttled(Some(4), Some(last), last + Duration::from_millis(249)));
+        assert!(!is_throttled(Some(4), Some(last), last + Duration::from_millis(250)));
+        assert!(!is_throttled(Some(4), Some(last), last + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn throttle_without_limit_or_history_lets_through() {
+        let now = Instant::now();
+        assert!(!is_throttled(None, Some(now), now));
+        assert!(!is_throttled(Some(1), None, now));
+        assert!(is_throttled(Some(0), Some(now), now + Duration::from_millis(999)));
+        assert!(!is_throttled(Some(0), Some(now), now + Duration::from_secs(1)));
     }
 }
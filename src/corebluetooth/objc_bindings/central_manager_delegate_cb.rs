@@ -1,18 +1,17 @@
 use crate::{
-    api::central_event::{CentralEvent, CentralState},
-    corebluetooth::objc_bindings::{
-        AdvertisementResolver, ServiceResolver,
-        mac_extensions_cb::{self},
-    },
+    api::central_event::CentralState,
+    corebluetooth::objc_bindings::mac_extensions_cb::{self},
 };
 
 use futures::executor;
 use log::trace;
-use objc2::runtime::{AnyObject, ProtocolObject};
-use objc2::{AnyThread, define_class, msg_send};
+use objc2::DowncastTarget;
+use objc2::runtime::AnyObject;
+use objc2::{AnyThread, Message, define_class, msg_send};
 use objc2::{DeclaredClass, rc::Retained};
 use objc2_core_bluetooth::{
     CBAdvertisementDataLocalNameKey, CBAdvertisementDataManufacturerDataKey,
+    CBAdvertisementDataOverflowServiceUUIDsKey,
     CBAdvertisementDataServiceDataKey, CBAdvertisementDataServiceUUIDsKey, CBCentralManager,
     CBCentralManagerDelegate, CBCharacteristic, CBDescriptor, CBManagerState, CBPeripheral,
     CBService, CBUUID,
@@ -21,8 +20,7 @@ use objc2_foundation::{
     NSArray, NSData, NSDictionary, NSError, NSNumber, NSObject, NSObjectProtocol, NSString,
 };
 use std::{collections::HashMap, fmt::Debug};
-use std::{convert::TryInto, sync::Arc};
-use tokio::sync::{Mutex, mpsc::Sender};
+use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
 // Instance Variables that are stored within the ObjC class allowing communication between Rust
@@ -30,8 +28,6 @@ use uuid::Uuid;
 #[derive(Debug)]
 pub struct IVars {
     pub sender: Sender<CentralManagerDelegateEvent>,
-    pub services_resolver: Arc<Mutex<ServiceResolver>>,
-    pub advertisement_resolver: Arc<Mutex<AdvertisementResolver>>,
 }
 
 define_class!(
@@ -70,7 +66,6 @@ define_class!(
                 "delegate_centralmanager_didconnectperipheral {}",
                 peripheral_debug(peripheral)
             );
-            unsafe { peripheral.setDelegate(Some(ProtocolObject::from_ref(self))) };
             unsafe { peripheral.discoverServices(None) }
             let retained_uuid = unsafe { &peripheral.identifier() };
             let peripheral_uuid = mac_extensions_cb::nsuuid_to_uuid(retained_uuid);
@@ -101,15 +96,11 @@ define_class!(
         fn delegate_centralmanager_didfailtoconnectperipheral_error(
             &self,
             _central: &CBCentralManager,
-            peripheral: &CBPeripheral,
-            error: Option<&NSError>,
+            _peripheral: &CBPeripheral,
+            _error: Option<&NSError>,
         ) {
             trace!("delegate_centralmanager_didfailtoconnectperipheral_error");
-            let retained_uuid = unsafe { &peripheral.identifier() };
-            let peripheral_uuid = mac_extensions_cb::nsuuid_to_uuid(retained_uuid);
-            let error_description = error.map(|error| error.localizedDescription().to_string());
             //TODO: Oneshot callback
-
         }
 
         #[unsafe(method(centralManager:didDiscoverPeripheral:advertisementData:RSSI:))]
@@ -125,68 +116,72 @@ define_class!(
                 peripheral_debug(peripheral)
             );
 
-            let local_name = unsafe {
-                adv_data
-                    .objectForKey(CBAdvertisementDataLocalNameKey)
-                    .and_then(|name_obj| {
-                        let name_nsstring: *const NSString =
-                            Retained::<AnyObject>::as_ptr(&name_obj) as *const NSString;
-                        mac_extensions_cb::nsstring_to_string(name_nsstring)
-                    })
-            }
-            .unwrap_or_else(|| String::from("Unknown"));
+            let local_name =
+                adv_value::<NSString>(adv_data, unsafe { CBAdvertisementDataLocalNameKey })
+                    .map(|name| name.to_string());
 
             let retained_uuid = unsafe { &peripheral.identifier() };
             let peripheral_uuid = mac_extensions_cb::nsuuid_to_uuid(retained_uuid);
 
             let rssi_value = rssi.as_i16();
 
+            // Overflow services are listed alongside the regular ones for scan filter matching
+            let mut advertised_services =
+                uuid_array(adv_data, unsafe { CBAdvertisementDataServiceUUIDsKey });
+            advertised_services.extend(uuid_array(adv_data, unsafe {
+                CBAdvertisementDataOverflowServiceUUIDsKey
+            }));
+
             self.send_event(CentralManagerDelegateEvent::DeviceDiscovered {
                 server: peripheral_uuid,
+                peripheral: peripheral.retain(),
+                services: advertised_services,
                 name: local_name,
                 rssi: rssi_value,
             });
 
             let manufacturer_data =
-                unsafe { adv_data.objectForKey(CBAdvertisementDataManufacturerDataKey) };
+                adv_value::<NSData>(adv_data, unsafe { CBAdvertisementDataManufacturerDataKey });
 
             if let Some(manufacturer_data) = manufacturer_data {
-                // SAFETY: manufacturer_data is `NSData`
-                let manufacturer_data_ptr: *const AnyObject = Retained::as_ptr(&manufacturer_data);
-                let manufacturer_data_nsdata: *const NSData = manufacturer_data_ptr.cast();
-                let manufacturer_data: &NSData = unsafe { &*manufacturer_data_nsdata };
-
-                if manufacturer_data.len() >= 2 {
-                    let (manufacturer_id, manufacturer_data) =
-                        unsafe { manufacturer_data.as_bytes_unchecked().split_at(2) };
-
-                    self.send_event(CentralManagerDelegateEvent::ManufacturerDataAdvertisement {
-                        server: peripheral_uuid,
-                        manufacturer_id: u16::from_le_bytes(manufacturer_id.try_into().unwrap()),
-                        manufacturer_data: Vec::from(manufacturer_data),
-                    });
+                // A bare company ID is still reported, with empty data. Anything shorter can't be
+                // attributed to a company.
+                match unsafe { manufacturer_data.as_bytes_unchecked() } {
+                    [id_low, id_high, data @ ..] => {
+                        self.send_event(CentralManagerDelegateEvent::ManufacturerDataAdvertisement {
+                            server: peripheral_uuid,
+                            manufacturer_id: u16::from_le_bytes([*id_low, *id_high]),
+                            manufacturer_data: Vec::from(data),
+                        });
+                    }
+                    bytes => trace!(
+                        "Ignoring manufacturer data of {} bytes from {}",
+                        bytes.len(),
+                        peripheral_uuid
+                    ),
                 }
             }
 
-            let service_data = unsafe { adv_data.objectForKey(CBAdvertisementDataServiceDataKey) };
+            let service_data =
+                adv_value::<NSDictionary>(adv_data, unsafe { CBAdvertisementDataServiceDataKey });
 
             if let Some(service_data) = service_data {
-                // SAFETY: service_data is `NSDictionary<CBUUID, NSData>`
-                let service_data: *const AnyObject = Retained::as_ptr(&service_data);
-                let service_data: *const NSDictionary<CBUUID, NSData> = service_data.cast();
-                let service_data: &NSDictionary<CBUUID, NSData> = unsafe { &*service_data };
-
                 let mut result = HashMap::new();
-                for cbuuid in service_data.keys() {
-                    if let Some(data_obj) = service_data.objectForKey(&cbuuid) {
-                        let data = unsafe {
-                            let data_ptr = Retained::as_ptr(&data_obj) as *const NSData;
-                            let nsdata = &*data_ptr;
-                            nsdata.as_bytes_unchecked().to_vec()
-                        };
-                        let service_uuid: Uuid =
-                            unsafe { mac_extensions_cb::cbuuid_to_uuid(&cbuuid) };
-                        result.insert(service_uuid, data);
+                for key in service_data.keys() {
+                    let Some(value) = service_data.objectForKey(&key) else {
+                        continue;
+                    };
+                    // Entries are expected to map a CBUUID to NSData, anything else is skipped
+                    match (key.downcast::<CBUUID>(), value.downcast::<NSData>()) {
+                        (Ok(cbuuid), Ok(data)) => {
+                            let service_uuid: Uuid =
+                                unsafe { mac_extensions_cb::cbuuid_to_uuid(&cbuuid) };
+                            result.insert(service_uuid, data.to_vec());
+                        }
+                        _ => trace!(
+                            "Ignoring malformed service data entry from {}",
+                            peripheral_uuid
+                        ),
                     }
                 }
 
@@ -196,34 +191,49 @@ define_class!(
                 });
             }
 
-            let services = unsafe { adv_data.objectForKey(CBAdvertisementDataServiceUUIDsKey) };
-            if let Some(services) = services {
-                // SAFETY: services is `NSArray<CBUUID>`
-                let services: *const AnyObject = Retained::as_ptr(&services);
-                let services: *const NSArray<CBUUID> = services.cast();
-                let services: &NSArray<CBUUID> = unsafe { &*services };
-
-                let mut service_uuids = Vec::new();
-                for cbuuid in services {
-                    let service_uuid: Uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&cbuuid) };
-                    service_uuids.push(service_uuid);
-                }
-
+            if adv_value::<NSArray>(adv_data, unsafe { CBAdvertisementDataServiceUUIDsKey }).is_some() {
                 self.send_event(CentralManagerDelegateEvent::ServicesAdvertisement {
                     server: peripheral_uuid,
-                    services: service_uuids,
+                    services: uuid_array(adv_data, unsafe { CBAdvertisementDataServiceUUIDsKey }),
                 });
             }
         }
     }
 );
 
+// Advertisement data comes straight from the air, so the class of every entry is checked
+// (isKindOfClass:) before it is used and an entry of an unexpected type is skipped
+fn adv_value<T: DowncastTarget>(
+    adv_data: &NSDictionary<NSString, AnyObject>,
+    key: &NSString,
+) -> Option<Retained<T>> {
+    let value = adv_data.objectForKey(key)?;
+    match value.downcast::<T>() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            trace!("Ignoring advertisement entry {} of an unexpected type", key);
+            None
+        }
+    }
+}
+
+// Read an NSArray<CBUUID> entry of the advertisement data, empty when absent. Elements that
+// aren't a CBUUID are skipped.
+fn uuid_array(adv_data: &NSDictionary<NSString, AnyObject>, key: &NSString) -> Vec<Uuid> {
+    let Some(uuids) = adv_value::<NSArray>(adv_data, key) else {
+        return Vec::new();
+    };
+    uuids
+        .iter()
+        .filter_map(|uuid| uuid.downcast::<CBUUID>().ok())
+        .map(|cbuuid| unsafe { mac_extensions_cb::cbuuid_to_uuid(&cbuuid) })
+        .collect()
+}
+
 impl CentralManagerDelegate {
     pub fn new(sender: Sender<CentralManagerDelegateEvent>) -> Retained<Self> {
         let this = CentralManagerDelegate::alloc().set_ivars(IVars {
             sender,
-            services_resolver: Arc::new(Mutex::new(ServiceResolver::new())),
-            advertisement_resolver: Arc::new(Mutex::new(AdvertisementResolver::new())),
         });
         unsafe { msg_send![super(this), init] }
     }
@@ -238,7 +248,7 @@ impl CentralManagerDelegate {
     }
 }
 
-fn localized_description(error: Option<&NSError>) -> String {
+pub fn localized_description(error: Option<&NSError>) -> String {
     if let Some(error) = error {
         error.localizedDescription().to_string()
     } else {
@@ -246,7 +256,7 @@ fn localized_description(error: Option<&NSError>) -> String {
     }
 }
 
-fn peripheral_debug(peripheral: &CBPeripheral) -> String {
+pub fn peripheral_debug(peripheral: &CBPeripheral) -> String {
     let uuid = unsafe { peripheral.identifier() }.UUIDString();
     if let Some(name) = unsafe { peripheral.name() } {
         format!("CBPeripheral({}, {})", name, uuid)
@@ -255,22 +265,22 @@ fn peripheral_debug(peripheral: &CBPeripheral) -> String {
     }
 }
 
-fn service_debug(service: &CBService) -> String {
+pub fn service_debug(service: &CBService) -> String {
     let uuid = unsafe { service.UUID().UUIDString() };
     format!("CBService({})", uuid)
 }
 
-fn characteristic_debug(characteristic: &CBCharacteristic) -> String {
+pub fn characteristic_debug(characteristic: &CBCharacteristic) -> String {
     let uuid = unsafe { characteristic.UUID().UUIDString() };
     format!("CBCharacteristic({})", uuid)
 }
 
-fn descriptor_debug(descriptor: &CBDescriptor) -> String {
+pub fn descriptor_debug(descriptor: &CBDescriptor) -> String {
     let uuid = unsafe { descriptor.UUID().UUIDString() };
     format!("CBDescriptor({})", uuid)
 }
 
-fn convert_state(cb_state: CBManagerState) -> CentralState {
+pub fn convert_state(cb_state: CBManagerState) -> CentralState {
     match cb_state {
         CBManagerState::Unknown => CentralState::Unknown,
         CBManagerState::Resetting => CentralState::Resetting,
@@ -288,7 +298,11 @@ fn convert_state(cb_state: CBManagerState) -> CentralState {
 pub enum CentralManagerDelegateEvent {
     DeviceDiscovered {
         server: Uuid,
-        name: String,
+        peripheral: Retained<CBPeripheral>,
+        // Advertised service UUIDs, including the overflow area
+        services: Vec<Uuid>,
+        // Advertised local name, if the advertisement had one
+        name: Option<String>,
         rssi: i16,
     },
     DeviceConnected {
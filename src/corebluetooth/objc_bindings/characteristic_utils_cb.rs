@@ -1,16 +1,15 @@
-use crate::gatt::{
-    characteristic::Characteristic,
-    descriptor::Descriptor,
-    properties::{AttributePermission, CharacteristicProperty},
+use crate::api::{
+    characteristic::{Characteristic, CharacteristicProperty},
+    descriptor::{AttributePermission, Descriptor},
 };
-use objc2::{rc::Retained, runtime::AnyObject, ClassType};
+use objc2::{AnyThread, rc::Retained, runtime::AnyObject};
 use objc2_core_bluetooth::{
     CBAttributePermissions, CBCharacteristicProperties, CBDescriptor, CBMutableCharacteristic,
     CBMutableDescriptor,
 };
 use objc2_foundation::{NSArray, NSData};
 
-use super::mac_extensions::uuid_to_cbuuid;
+use super::mac_extensions_cb::uuid_to_cbuuid;
 
 pub fn parse_characteristic(characteristic: &Characteristic) -> Retained<CBMutableCharacteristic> {
     unsafe {
@@ -18,14 +17,14 @@ pub fn parse_characteristic(characteristic: &Characteristic) -> Retained<CBMutab
             .properties
             .iter()
             .fold(CBCharacteristicProperties::empty(), |acc, property| {
-                acc | property.clone().to_cb_property()
+                acc | property.to_cb_property()
             });
 
         let permissions = characteristic
             .permissions
             .iter()
             .fold(CBAttributePermissions::empty(), |acc, permission| {
-                acc | permission.clone().to_attribute_permission()
+                acc | permission.to_attribute_permission()
             });
 
         let value_data = characteristic
@@ -41,19 +40,18 @@ pub fn parse_characteristic(characteristic: &Characteristic) -> Retained<CBMutab
             permissions,
         );
 
-        let descriptors: Retained<NSArray<CBDescriptor>> = NSArray::from_vec(
-            characteristic
-                .descriptors
-                .iter()
-                .map(|desc| parse_descriptor(desc))
-                .collect(),
-        );
+        let descriptors: Vec<Retained<CBDescriptor>> = characteristic
+            .descriptors
+            .iter()
+            .map(parse_descriptor)
+            .collect();
+        let descriptors: Retained<NSArray<CBDescriptor>> = NSArray::from_retained_slice(&descriptors);
 
         mutable_char.setDescriptors(Some(&descriptors));
         if !descriptors.is_empty() {
             log::debug!("DescriptorAdded");
         }
-        return mutable_char;
+        mutable_char
     }
 }
 
@@ -64,54 +62,74 @@ pub fn parse_descriptor(descriptor: &Descriptor) -> Retained<CBDescriptor> {
             .as_ref()
             .map(|value| NSData::from_vec(value.clone()));
 
-        return Retained::into_super(CBMutableDescriptor::initWithType_value(
+        Retained::into_super(CBMutableDescriptor::initWithType_value(
             CBMutableDescriptor::alloc(),
             &uuid_to_cbuuid(descriptor.uuid),
             value_data.as_ref().map(|data| data as &AnyObject),
-        ));
+        ))
     }
 }
 
 impl CharacteristicProperty {
-    fn to_cb_property(self) -> CBCharacteristicProperties {
-        return match self {
+    const ALL: [CharacteristicProperty; 10] = [
+        CharacteristicProperty::Broadcast,
+        CharacteristicProperty::Read,
+        CharacteristicProperty::WriteWithoutResponse,
+        CharacteristicProperty::Write,
+        CharacteristicProperty::AuthenticatedSignedWrites,
+        CharacteristicProperty::Notify,
+        CharacteristicProperty::NotifyEncryptionRequired,
+        CharacteristicProperty::Indicate,
+        CharacteristicProperty::IndicateEncryptionRequired,
+        CharacteristicProperty::ExtendedProperties,
+    ];
+
+    pub fn from_cb_properties(properties: CBCharacteristicProperties) -> Vec<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|property| properties.contains(property.to_cb_property()))
+            .collect()
+    }
+
+    fn to_cb_property(&self) -> CBCharacteristicProperties {
+        match self {
             CharacteristicProperty::Broadcast => {
-                CBCharacteristicProperties::CBCharacteristicPropertyBroadcast
+                CBCharacteristicProperties::Broadcast
             }
             CharacteristicProperty::Read => {
-                CBCharacteristicProperties::CBCharacteristicPropertyRead
+                CBCharacteristicProperties::Read
             }
             CharacteristicProperty::WriteWithoutResponse => {
-                CBCharacteristicProperties::CBCharacteristicPropertyWriteWithoutResponse
+                CBCharacteristicProperties::WriteWithoutResponse
             }
             CharacteristicProperty::Write => {
-                CBCharacteristicProperties::CBCharacteristicPropertyWrite
+                CBCharacteristicProperties::Write
             }
             CharacteristicProperty::Notify => {
-                CBCharacteristicProperties::CBCharacteristicPropertyNotify
+                CBCharacteristicProperties::Notify
             }
             CharacteristicProperty::NotifyEncryptionRequired => {
-                CBCharacteristicProperties::CBCharacteristicPropertyNotifyEncryptionRequired
+                CBCharacteristicProperties::NotifyEncryptionRequired
             }
             CharacteristicProperty::Indicate => {
-                CBCharacteristicProperties::CBCharacteristicPropertyIndicate
+                CBCharacteristicProperties::Indicate
             }
             CharacteristicProperty::IndicateEncryptionRequired => {
-                CBCharacteristicProperties::CBCharacteristicPropertyIndicateEncryptionRequired
+                CBCharacteristicProperties::IndicateEncryptionRequired
             }
             CharacteristicProperty::AuthenticatedSignedWrites => {
-                CBCharacteristicProperties::CBCharacteristicPropertyAuthenticatedSignedWrites
+                CBCharacteristicProperties::AuthenticatedSignedWrites
             }
             CharacteristicProperty::ExtendedProperties => {
-                CBCharacteristicProperties::CBCharacteristicPropertyExtendedProperties
+                CBCharacteristicProperties::ExtendedProperties
             }
-        };
+        }
     }
 }
 
 impl AttributePermission {
-    fn to_attribute_permission(self) -> CBAttributePermissions {
-        return match self {
+    fn to_attribute_permission(&self) -> CBAttributePermissions {
+        match self {
             AttributePermission::Readable => CBAttributePermissions::Readable,
             AttributePermission::Writeable => CBAttributePermissions::Writeable,
             AttributePermission::ReadEncryptionRequired => {
@@ -120,6 +138,6 @@ impl AttributePermission {
             AttributePermission::WriteEncryptionRequired => {
                 CBAttributePermissions::WriteEncryptionRequired
             }
-        };
+        }
     }
 }
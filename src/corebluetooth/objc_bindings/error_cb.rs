@@ -1,4 +1,4 @@
-use crate::error::{Error, ErrorType};
+use crate::{Error, ErrorType};
 use tokio::sync::{mpsc, oneshot};
 
 impl<T> From<mpsc::error::SendError<T>> for Error {
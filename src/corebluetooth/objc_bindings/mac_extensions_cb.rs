@@ -1,15 +1,15 @@
-use std::ffi::CStr;
-
 use objc2::rc::Retained;
 use objc2_core_bluetooth::{CBCharacteristic, CBService, CBUUID};
-use objc2_foundation::{NSString, NSUUID};
+use objc2_foundation::{NSData, NSString, NSUUID};
 use uuid::Uuid;
 
+use crate::api::central_event::Payload;
+
 // NOTE: Bluetooth Short Sevice UUIDs follow this pattern:
 // xxxxxxxx-0000-1000-8000-00805F9B34FB
 // Last 12 bytes are always the same 
-const BLUETOOTH_BASE_LOWER_96: u128 = 0x0000_1000_8000_00805F9B34FB;
-const LOWER_96_BIT_MASK: u128 = 0xFFFFFFFFFFFFFFFFFFFFFFFF;
+const BLUETOOTH_BASE_LOWER_96: u128 = 0x0000_1000_8000_0080_5F9B_34FB;
+const LOWER_96_BIT_MASK: u128 = 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF;
 
 pub fn uuid_to_cbuuid(uuid: Uuid) -> Retained<CBUUID> {
     unsafe { CBUUID::UUIDWithString(&NSString::from_str(&uuid.to_short_string())) }
@@ -26,12 +26,32 @@ pub unsafe fn cbuuid_to_uuid(cbuuid: &CBUUID) -> Uuid {
     }
 }
 
-pub unsafe fn nsstring_to_string(nsstring: *const NSString) -> Option<String> {
-    unsafe {
-        nsstring
-            .as_ref()
-            .and_then(|ns| CStr::from_ptr(ns.UTF8String()).to_str().ok())
-            .map(String::from)
+#[cfg(not(feature = "bytes"))]
+pub fn nsdata_to_payload(data: Retained<NSData>) -> Payload {
+    data.to_vec()
+}
+
+// NOTE: The NSData handed out by CoreBluetooth is immutable, so the Bytes can safely borrow its
+// buffer for as long as the Retained keeps it alive instead of copying it.
+#[cfg(feature = "bytes")]
+pub fn nsdata_to_payload(data: Retained<NSData>) -> Payload {
+    bytes::Bytes::from_owner(NSDataOwner(data))
+}
+
+#[cfg(feature = "bytes")]
+struct NSDataOwner(Retained<NSData>);
+
+// SAFETY: The wrapped NSData is immutable (see nsdata_to_payload) and immutable Foundation
+// objects are safe to retain, release and read from any thread.
+#[cfg(feature = "bytes")]
+unsafe impl Send for NSDataOwner {}
+#[cfg(feature = "bytes")]
+unsafe impl Sync for NSDataOwner {}
+
+#[cfg(feature = "bytes")]
+impl AsRef<[u8]> for NSDataOwner {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { self.0.as_bytes_unchecked() }
     }
 }
 
@@ -54,24 +74,17 @@ impl UuidExtension for &CBCharacteristic {
 impl UuidExtension for &CBUUID {
     fn get_uuid(self) -> Uuid {
         let uuid_str = unsafe { self.UUIDString() }.to_string();
-        return Uuid::from_string(uuid_str);
+        Uuid::from_string(uuid_str)
     }
 }
 
 pub trait CbuuidConvert {
-    fn from_short(uuid: u32) -> Uuid;
-
     fn from_string(uuid_str: String) -> Uuid;
 
     fn to_short_string(&self) -> String;
 }
 
 impl CbuuidConvert for Uuid {
-    fn from_short(short_uuid: u32) -> Uuid {
-        let full_uuid = ((short_uuid as u128) << 96) | BLUETOOTH_BASE_LOWER_96;
-        Uuid::from_u128(full_uuid)
-    }
-
     // NOTE: CoreBluetooth uses 4char (16 bit) Short UUIDs for Standard Service Identification, to be data efficient.
     fn from_string(uuid_string: String) -> Uuid {
         match Uuid::parse_str(&uuid_string) {
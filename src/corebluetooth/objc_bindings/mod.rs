@@ -1,25 +1,46 @@
 use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
 
+use tokio::runtime::{self, Runtime};
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
+use crate::api::config::{DEFAULT_QUEUE_LABEL, RuntimeFlavor};
+
+pub mod adapter_cb;
 mod central_manager_delegate_cb;
 pub mod central_manager_cb;
-mod characteristic_utils_cb;
+pub mod characteristic_utils_cb;
 mod error_cb;
-mod mac_extensions_cb;
+pub mod mac_extensions_cb;
 mod mac_utils_cb;
 mod peripheral_manager_delegate_cb;
 pub mod peripheral_manager_cb;
 pub mod peripheral_delegate_cb;
 mod peripheral_cb;
 
-#[derive(Debug)]
-pub struct ServiceResolver(HashMap<Uuid, oneshot::Sender<Option<String>>>);
+// Build the runtime driving a background manager thread
+pub fn build_runtime(flavor: &RuntimeFlavor) -> io::Result<Runtime> {
+    match flavor {
+        RuntimeFlavor::CurrentThread => runtime::Builder::new_current_thread().enable_time().build(),
+        RuntimeFlavor::MultiThread { workers } => runtime::Builder::new_multi_thread()
+            .worker_threads(*workers)
+            .enable_time()
+            .build(),
+    }
+}
 
+// Dispatch queue label from the config, a label with an interior NUL falls back to the default
+pub fn queue_label(label: &str) -> CString {
+    CString::new(label).unwrap_or_else(|_| {
+        log::warn!("Invalid dispatch queue label {:?}, using the default", label);
+        CString::new(DEFAULT_QUEUE_LABEL).unwrap()
+    })
+}
 
 #[derive(Debug)]
-pub struct AdvertisementResolver(Option<oneshot::Sender<Option<String>>>);
+pub struct ServiceResolver(HashMap<Uuid, oneshot::Sender<Option<String>>>);
 
 
 impl ServiceResolver {
@@ -31,10 +52,6 @@ impl ServiceResolver {
         self.0.contains_key(service_uuid)
     }
 
-    pub fn has_pending(&self) -> bool {
-        !self.0.is_empty()
-    }
-
     pub fn register(&mut self, service_uuid: Uuid, sender: oneshot::Sender<Option<String>>) {
         self.0.insert(service_uuid, sender);
     }
@@ -42,14 +59,6 @@ impl ServiceResolver {
      pub fn take(&mut self, service_uuid: &Uuid) -> Option<oneshot::Sender<Option<String>>> {
         self.0.remove(service_uuid)
     }
-
-    pub fn cancel(&mut self, service_uuid: &Uuid) -> bool {
-        self.0.remove(service_uuid).is_some()
-    }
-
-    pub fn count(&self) -> usize {
-        self.0.len()
-    }
 }
 
 impl Default for ServiceResolver {
@@ -57,25 +66,3 @@ impl Default for ServiceResolver {
         Self::new()
     }
 }
-
-impl AdvertisementResolver {
-    pub fn new() -> Self {
-        Self(None)
-    }
-
-    pub fn is_waiting(&self) -> bool {
-        self.0.is_some()
-    }
-
-    pub fn register(&mut self, sender: oneshot::Sender<Option<String>>) {
-        self.0 = Some(sender);
-    }
-
-    pub fn take(&mut self) -> Option<oneshot::Sender<Option<String>>> {
-        self.0.take()
-    }
-
-    pub fn cancel(&mut self) {
-        self.0 = None;
-    }
-}
@@ -1,252 +1,1304 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future;
+use std::time::Instant;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
-use objc2::{msg_send, rc::Retained};
-use objc2_core_bluetooth::{CBCharacteristic, CBDescriptor, CBPeripheral, CBService};
+use objc2::rc::Retained;
+use objc2_core_bluetooth::{
+    CBCentralManager, CBCharacteristic, CBCharacteristicProperties, CBCharacteristicWriteType,
+    CBDescriptor, CBPeripheral, CBPeripheralState, CBService,
+};
+use objc2_foundation::NSData;
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
     oneshot,
 };
+use tokio::time::{self, Interval, MissedTickBehavior};
 use uuid::Uuid;
 
 use crate::{
+    Error, ErrorType, Result,
     api::{
-        central_event::CentralEvent, characteristic::Characteristic, descriptor::Descriptor,
+        central::{ConnectOptions, PeripheralNames},
+        central_event::{CentralEvent, DisconnectReason, Payload, payload_into_vec},
+        characteristic::{
+            ATT_HEADER_SIZE, Characteristic, CharacteristicProperty, CharacteristicWriteType,
+        },
+        config::SupervisionConfig,
+        descriptor::Descriptor,
         service::Service,
     },
     corebluetooth::{
-        central_manager::PeripheralRemoteCommand,
-        objc_bindings::peripheral_delegate_cb::{PeripheralDelegate, PeripheralDelegateEvent},
+        central_manager::{NotificationSender, PeripheralRemoteCommand},
+        objc_bindings::{
+            mac_extensions_cb,
+            peripheral_delegate_cb::{PeripheralDelegate, PeripheralDelegateEvent},
+        },
     },
 };
 
-struct Peripheral {
+// Keep-alive state used to detect a link that went away without CoreBluetooth noticing
+struct Supervision {
+    ticker: Interval,
+    failure_threshold: u32,
+    consecutive_failures: u32,
+    awaiting_rssi: bool,
+}
+
+// Notification state of a subscribed characteristic
+struct Subscription {
+    changes_only: bool,
+    last_value: Option<Payload>,
+    // Dedicated stream of this subscription, see PeripheralRemote::connect_subscribe
+    notification_tx: Option<NotificationSender>,
+    // Sequence number of the next forwarded notification
+    next_sequence: u64,
+}
+
+// Shared by every peripheral so notifications can be ordered across characteristics
+static NOTIFICATION_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+impl Subscription {
+    // Number the next forwarded notification within this subscription and across all of them
+    fn take_sequence(&mut self) -> (u64, u64) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        (sequence, NOTIFICATION_SEQUENCE.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// What the central manager hands to the task running a peripheral, the task ends and forgets the
+// peripheral once the central manager drops its sender
+pub enum PeripheralMessage {
+    Command(PeripheralRemoteCommand),
+    Advertised(Option<String>),
+    Connected,
+    Disconnected,
+}
+
+// Whichever of a peripheral's inputs was ready first
+enum Next {
+    Message(Option<PeripheralMessage>),
+    Event(PeripheralBackendEvent),
+}
+
+pub enum PeripheralBackendEvent {
+    Delegate(PeripheralDelegateEvent),
+    SupervisionTick,
+}
+
+pub struct Peripheral {
+    uuid: Uuid,
     peripheral: Retained<CBPeripheral>,
-    delegate: Retained<PeripheralDelegate>,
+    manager: Retained<CBCentralManager>,
+    // Kept alive alongside the peripheral as CoreBluetooth only holds a weak reference to it
+    _delegate: Retained<PeripheralDelegate>,
     central_tx: Sender<CentralEvent>,
+    mtu: Arc<AtomicUsize>,
+    supervision: Option<Supervision>,
+    disconnect_reason: Option<DisconnectReason>,
+    // Set on the first connection of the session
+    has_connected: bool,
+    // Local name of the latest advertisement, if it had one
+    advertised_name: Option<String>,
     cached_services: HashMap<Uuid, Retained<CBService>>,
     cached_characteristics: HashMap<Uuid, Retained<CBCharacteristic>>,
-    cached_descriptors: HashMap<Uuid, Retained<CBDescriptor>>,
-    remote_command_rx: Receiver<PeripheralRemoteCommand>,
+    // Keyed by characteristic and descriptor, every notifiable characteristic has its own 0x2902
+    cached_descriptors: HashMap<(Uuid, Uuid), Retained<CBDescriptor>>,
+    included_services: HashMap<Uuid, Vec<Uuid>>,
+    service_characteristics: HashMap<Uuid, Vec<Uuid>>,
+    characteristic_descriptors: HashMap<Uuid, Vec<Uuid>>,
+    // Services and characteristics still waiting on their children to be discovered
+    pending_services: HashSet<Uuid>,
+    pending_characteristics: HashSet<Uuid>,
+    // Services whose included services are still being discovered
+    pending_included: HashSet<Uuid>,
+    // Set by cancel_discovery until the next discovery starts
+    discovery_cancelled: bool,
+    // Reads failed by cancel_all_operations whose answer may still arrive, it is dropped rather
+    // than taken for a notification
+    cancelled_reads: HashSet<Uuid>,
+    subscriptions: HashMap<Uuid, Subscription>,
+    // Options of the latest connect
+    connect_options: ConnectOptions,
+    // Characteristics still to be read before a discovery completes, None when not reading
+    discovery_reads: Option<VecDeque<Uuid>>,
+    discovery_read_in_flight: Option<Uuid>,
     corebluetooth_delegate_rx: Receiver<PeripheralDelegateEvent>,
-    service_discovery_resolver: Option<oneshot::Sender<Result<Vec<Service>, String>>>,
-    characteristic_discovery_resolver:
-        HashMap<Uuid, oneshot::Sender<Result<Vec<Characteristic>, String>>>,
-    descriptor_discovery_resolver:
-        HashMap<(Uuid, Uuid), oneshot::Sender<Result<Vec<Descriptor>, String>>>,
-    read_resolver: HashMap<Uuid, oneshot::Sender<Result<Vec<u8>, String>>>,
-    write_resolver: HashMap<Uuid, oneshot::Sender<Result<(), String>>>,
-    subscribe_resolver: HashMap<Uuid, oneshot::Sender<Result<(), String>>>,
+    connect_resolver: Option<oneshot::Sender<Result<()>>>,
+    disconnect_resolver: Option<oneshot::Sender<Result<()>>>,
+    service_discovery_resolver: Option<oneshot::Sender<Result<Vec<Service>>>>,
+    // One read per characteristic, CoreBluetooth's answer doesn't say which read it belongs to
+    read_resolver: HashMap<Uuid, oneshot::Sender<Result<Vec<u8>>>>,
+    // Pending writes with response along with the number of bytes being written and when the
+    // write was handed to CoreBluetooth
+    write_resolver: HashMap<Uuid, (oneshot::Sender<Result<usize>>, usize, Instant)>,
+    // Queued per characteristic, CoreBluetooth confirms every setNotifyValue in order
+    subscribe_resolver: HashMap<Uuid, VecDeque<oneshot::Sender<Result<()>>>>,
+    unsubscribe_resolver: HashMap<Uuid, oneshot::Sender<Result<()>>>,
+    descriptor_read_resolver: HashMap<(Uuid, Uuid), oneshot::Sender<Result<Vec<u8>>>>,
+    descriptor_write_resolver: HashMap<(Uuid, Uuid), oneshot::Sender<Result<()>>>,
 }
 
 impl Peripheral {
     pub fn new(
         peripheral: Retained<CBPeripheral>,
+        manager: Retained<CBCentralManager>,
         central_tx: Sender<CentralEvent>,
-        remote_command_rx: Receiver<PeripheralRemoteCommand>,
+        mtu: Arc<AtomicUsize>,
+        supervision: Option<SupervisionConfig>,
     ) -> Self {
         let (delegate_tx, delegate_rx) = mpsc::channel::<PeripheralDelegateEvent>(256);
 
         let delegate: Retained<PeripheralDelegate> = PeripheralDelegate::new(delegate_tx);
 
-        // attach this Rust instance with the Delegate in objc2 runtime
-        unsafe {
-            msg_send![&peripheral, setDelegate: &*delegate];
-        }
+        // The delegate is owned by this struct so it lives exactly as long as the CBPeripheral
+        // is managed here, it is detached again on drop.
+        delegate.attach(&peripheral);
+
+        let supervision = supervision.map(|config| {
+            let mut ticker = time::interval(config.interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            Supervision {
+                ticker,
+                failure_threshold: config.failure_threshold,
+                consecutive_failures: 0,
+                awaiting_rssi: false,
+            }
+        });
+
+        let identifier = unsafe { peripheral.identifier() };
+        let uuid = mac_extensions_cb::nsuuid_to_uuid(&identifier);
 
         Self {
+            uuid,
             peripheral,
-            delegate,
+            manager,
+            _delegate: delegate,
             central_tx,
-            remote_command_rx,
+            mtu,
+            supervision,
+            disconnect_reason: None,
+            has_connected: false,
+            advertised_name: None,
             corebluetooth_delegate_rx: delegate_rx,
             cached_services: HashMap::new(),
             cached_characteristics: HashMap::new(),
             cached_descriptors: HashMap::new(),
+            included_services: HashMap::new(),
+            service_characteristics: HashMap::new(),
+            characteristic_descriptors: HashMap::new(),
+            pending_services: HashSet::new(),
+            pending_characteristics: HashSet::new(),
+            pending_included: HashSet::new(),
+            discovery_cancelled: false,
+            cancelled_reads: HashSet::new(),
+            subscriptions: HashMap::new(),
+            connect_options: ConnectOptions::default(),
+            discovery_reads: None,
+            discovery_read_in_flight: None,
+            connect_resolver: None,
+            disconnect_resolver: None,
             service_discovery_resolver: None,
-            characteristic_discovery_resolver: HashMap::new(),
-            descriptor_discovery_resolver: HashMap::new(),
             read_resolver: HashMap::new(),
             write_resolver: HashMap::new(),
             subscribe_resolver: HashMap::new(),
+            unsubscribe_resolver: HashMap::new(),
+            descriptor_read_resolver: HashMap::new(),
+            descriptor_write_resolver: HashMap::new(),
+        }
+    }
+
+    // Every peripheral runs on its own task so a slow operation or a lagging notification
+    // consumer on one device doesn't hold up the others. Ends once the peripheral is forgotten or
+    // the central manager goes away.
+    pub async fn run(mut self, mut message_rx: Receiver<PeripheralMessage>) {
+        let mut messages_first = false;
+        loop {
+            // Take turns on which side is polled first. A plain select picks a random ready
+            // branch, so under a flood of notifications a command could still wait for many
+            // rounds. Alternating bounds that to a single delegate event.
+            messages_first = !messages_first;
+            let next = if messages_first {
+                tokio::select! {
+                    biased;
+                    message = message_rx.recv() => Next::Message(message),
+                    event = self.next_event() => Next::Event(event),
+                }
+            } else {
+                tokio::select! {
+                    biased;
+                    event = self.next_event() => Next::Event(event),
+                    message = message_rx.recv() => Next::Message(message),
+                }
+            };
+            match next {
+                Next::Message(Some(message)) => self.handle_message(message).await,
+                Next::Message(None) => {
+                    self.forget();
+                    return;
+                }
+                Next::Event(event) => self.handle_event(event).await,
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, message: PeripheralMessage) {
+        match message {
+            PeripheralMessage::Command(command) => self.handle_command(command),
+            PeripheralMessage::Advertised(name) => self.advertised(name),
+            PeripheralMessage::Connected => {
+                let reconnect = self.connected();
+                let event = CentralEvent::DeviceConnected { server: self.uuid, reconnect };
+                if let Err(e) = self.central_tx.send(event).await {
+                    log::error!("Error sending central event: {}", e);
+                }
+            }
+            PeripheralMessage::Disconnected => {
+                let reason = self.disconnected();
+                let event = CentralEvent::DeviceDisconnected { server: self.uuid, reason };
+                if let Err(e) = self.central_tx.send(event).await {
+                    log::error!("Error sending central event: {}", e);
+                }
+            }
+        }
+    }
+
+    // Commands are routed here by the central manager based on their peripheral UUID
+    fn handle_command(&mut self, command: PeripheralRemoteCommand) {
+        match command {
+            PeripheralRemoteCommand::ConnectDevice { options, responder, .. } => self.connect(options, responder),
+            PeripheralRemoteCommand::DisconnectDevice { responder, .. } => self.disconnect(responder),
+            PeripheralRemoteCommand::DiscoverServices { responder, .. } => self.discover_services(responder),
+            PeripheralRemoteCommand::RefreshServices { responder, .. } => self.refresh_services(responder),
+            PeripheralRemoteCommand::CancelDiscovery { responder, .. } => {
+                self.cancel_discovery();
+                let _ = responder.send(Ok(()));
+            }
+            PeripheralRemoteCommand::CancelAllOperations { responder, .. } => {
+                self.cancel_all_operations();
+                let _ = responder.send(Ok(()));
+            }
+            PeripheralRemoteCommand::ReadCharacteristicValue { characteristic_uuid, responder, .. } => self.read_characteristic(characteristic_uuid, responder),
+            PeripheralRemoteCommand::WriteCharacteristicValue { characteristic_uuid, data, write_type, responder, .. } => self.write_characteristic(characteristic_uuid, data, write_type, responder),
+            PeripheralRemoteCommand::SubscribeCharacteristic { characteristic_uuid, changes_only, notification_tx, responder, .. } => self.subscribe(characteristic_uuid, changes_only, notification_tx, responder),
+            PeripheralRemoteCommand::UnsubscribeCharacteristic { characteristic_uuid, responder, .. } => self.unsubscribe(characteristic_uuid, responder),
+            PeripheralRemoteCommand::IsConnected { responder, .. } => { let _ = responder.send(Ok(self.is_connected())); },
+            PeripheralRemoteCommand::GetNames { responder, .. } => { let _ = responder.send(Ok(self.names())); },
+            PeripheralRemoteCommand::ReadDescriptorValue { characteristic_uuid, descriptor_uuid, responder, .. } => self.read_descriptor((characteristic_uuid, descriptor_uuid), responder),
+            PeripheralRemoteCommand::WriteDescriptorValue { characteristic_uuid, descriptor_uuid, data, responder, .. } => self.write_descriptor((characteristic_uuid, descriptor_uuid), data, responder),
         }
     }
 
-    async fn handle_event(&mut self) {
+    // Wait for the next delegate event or supervision tick. Only receives so it is cancel safe,
+    // run drops it whenever a message comes in first.
+    async fn next_event(&mut self) -> PeripheralBackendEvent {
         tokio::select! {
-        // Match events from above
-        Some(manager_command) = self.remote_command_rx.recv() => {
-            match manager_command {
-                PeripheralRemoteCommand::ConnectDevice { peripheral_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::DisconnectDevice { peripheral_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::ReadCharacteristicValue { peripheral_uuid, service_uuid, characteristic_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::WriteCharacteristicValue { peripheral_uuid, service_uuid, characteristic_uuid, data, write_type, responder } => todo!(),
-                PeripheralRemoteCommand::SubscribeCharacteristic { peripheral_uuid, service_uuid, characteristic_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::UnsubscribeCharacteristic { peripheral_uuid, service_uuid, characteristic_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::IsConnected { peripheral_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::ReadDescriptorValue { peripheral_uuid, service_uuid, characteristic_uuid, descriptor_uuid, responder } => todo!(),
-                PeripheralRemoteCommand::WriteDescriptorValue { peripheral_uuid, service_uuid, characteristic_uuid, descriptor_uuid, data, responder } => todo!(),
+            Some(delegate_event) = self.corebluetooth_delegate_rx.recv() => PeripheralBackendEvent::Delegate(delegate_event),
+            // Keep-alive for connection supervision, never resolves when supervision is disabled
+            _ = supervision_tick(&mut self.supervision) => PeripheralBackendEvent::SupervisionTick,
+        }
+    }
+
+    async fn handle_event(&mut self, event: PeripheralBackendEvent) {
+        match event {
+            // Match events from Corebluetooth delegate
+            PeripheralBackendEvent::Delegate(delegate_event) => match delegate_event {
+                PeripheralDelegateEvent::DiscoveredServices { services, error } => self.discovered_services(services, error).await,
+                PeripheralDelegateEvent::ServicesModified { invalidated_services } => self.services_modified(invalidated_services).await,
+                PeripheralDelegateEvent::DiscoveredIncludedServices { service_uuid, included_services, error } => self.discovered_included_services(service_uuid, included_services, error).await,
+                PeripheralDelegateEvent::DiscoveredCharacteristics { service_uuid, characteristics, error } => self.discovered_characteristics(service_uuid, characteristics, error).await,
+                PeripheralDelegateEvent::DiscoveredCharacteristicDescriptors { service_uuid, characteristic_uuid, descriptors, error } => self.discovered_descriptors(service_uuid, characteristic_uuid, descriptors, error).await,
+                PeripheralDelegateEvent::CharacteristicSubscribed { characteristic_uuid, error, .. } => self.resolve_subscribe(characteristic_uuid, error),
+                PeripheralDelegateEvent::CharacteristicUnsubscribed { characteristic_uuid, error, .. } => self.characteristic_unsubscribed(characteristic_uuid, error),
+                PeripheralDelegateEvent::CharacteristicNotified { service_uuid, characteristic_uuid, value, error } => self.characteristic_notified(service_uuid, characteristic_uuid, value, error).await,
+                PeripheralDelegateEvent::CharacteristicWritten { characteristic_uuid, error, .. } => self.characteristic_written(characteristic_uuid, error).await,
+                PeripheralDelegateEvent::DescriptorNotified { characteristic_uuid, descriptor_uuid, value, error, .. } => resolve(self.descriptor_read_resolver.remove(&(characteristic_uuid, descriptor_uuid)), value, error),
+                PeripheralDelegateEvent::DescriptorWritten { characteristic_uuid, descriptor_uuid, error, .. } => resolve(self.descriptor_write_resolver.remove(&(characteristic_uuid, descriptor_uuid)), (), error),
+                PeripheralDelegateEvent::RssiRead { rssi, error } => self.rssi_read(rssi, error),
+            },
+            PeripheralBackendEvent::SupervisionTick => self.supervise(),
+        }
+    }
+
+    fn connect(&mut self, options: ConnectOptions, responder: oneshot::Sender<Result<()>>) {
+        self.connect_options = options;
+        if self.is_connected() {
+            let _ = responder.send(Ok(()));
+            return;
+        }
+        self.connect_resolver = Some(responder);
+        unsafe {
+            self.manager
+                .connectPeripheral_options(&self.peripheral, None)
+        };
+    }
+
+    // Once didConnectPeripheral fires for this peripheral, returns whether the peripheral had
+    // been connected before
+    fn connected(&mut self) -> bool {
+        resolve(self.connect_resolver.take(), (), None);
+        std::mem::replace(&mut self.has_connected, true)
+    }
+
+    fn disconnect(&mut self, responder: oneshot::Sender<Result<()>>) {
+        if !self.is_connected() {
+            let _ = responder.send(Ok(()));
+            return;
+        }
+        self.disconnect_resolver = Some(responder);
+        self.disconnect_reason = Some(DisconnectReason::Requested);
+        unsafe { self.manager.cancelPeripheralConnection(&self.peripheral) };
+    }
+
+    fn discover_services(&mut self, responder: oneshot::Sender<Result<Vec<Service>>>) {
+        self.discovery_cancelled = false;
+        self.discovery_reads = None;
+        self.discovery_read_in_flight = None;
+        self.service_discovery_resolver = Some(responder);
+        unsafe { self.peripheral.discoverServices(None) };
+    }
+
+    // NOTE: CoreBluetooth can't stop a discovery in flight, so the waiting future is failed and
+    // the results still arriving for this discovery are ignored.
+    fn cancel_discovery(&mut self) {
+        // Only results of a running discovery are ignored, with none in flight nothing is left
+        // to arrive
+        let in_flight = self.service_discovery_resolver.is_some()
+            || !self.pending_services.is_empty()
+            || !self.pending_characteristics.is_empty()
+            || !self.pending_included.is_empty()
+            || self.discovery_reads.is_some();
+        let error = Error::from_string(
+            "Service discovery cancelled".to_string(),
+            ErrorType::Cancelled,
+        );
+        fail(self.service_discovery_resolver.take(), &error);
+        self.pending_services.clear();
+        self.pending_characteristics.clear();
+        self.pending_included.clear();
+        self.discovery_reads = None;
+        self.discovery_cancelled = in_flight;
+    }
+
+    fn refresh_services(&mut self, responder: oneshot::Sender<Result<Vec<Service>>>) {
+        self.clear_cache();
+        self.discover_services(responder);
+    }
+
+    fn clear_cache(&mut self) {
+        self.cached_services.clear();
+        self.cached_characteristics.clear();
+        self.cached_descriptors.clear();
+        self.included_services.clear();
+        self.service_characteristics.clear();
+        self.characteristic_descriptors.clear();
+        self.pending_services.clear();
+        self.pending_characteristics.clear();
+        self.pending_included.clear();
+    }
+
+    // The invalidated services are gone until rediscovered, their characteristics and
+    // descriptors stay cached until the next refresh as they can no longer be reached anyway.
+    async fn services_modified(&mut self, invalidated_services: Vec<Uuid>) {
+        for service_uuid in invalidated_services.iter() {
+            self.cached_services.remove(service_uuid);
+            self.service_characteristics.remove(service_uuid);
+            self.included_services.remove(service_uuid);
+        }
+        let event = CentralEvent::ServicesModified {
+            server: self.uuid,
+            invalidated_services,
+        };
+        if let Err(e) = self.central_tx.send(event).await {
+            log::error!("Error sending central event: {}", e);
+        }
+    }
+
+    fn read_characteristic(
+        &mut self,
+        characteristic_uuid: Uuid,
+        responder: oneshot::Sender<Result<Vec<u8>>>,
+    ) {
+        let Some(characteristic) = self.cached_characteristics.get(&characteristic_uuid) else {
+            let _ = responder.send(Err(not_discovered(characteristic_uuid)));
+            return;
+        };
+        let claimed = claim(&mut self.read_resolver, characteristic_uuid, "read", |responder| {
+            responder.is_closed()
+        });
+        if let Err(error) = claimed {
+            let _ = responder.send(Err(error));
+            return;
+        }
+        unsafe { self.peripheral.readValueForCharacteristic(characteristic) };
+        self.read_resolver.insert(characteristic_uuid, responder);
+    }
+
+    fn write_characteristic(
+        &mut self,
+        characteristic_uuid: Uuid,
+        data: Vec<u8>,
+        write_type: CharacteristicWriteType,
+        responder: oneshot::Sender<Result<usize>>,
+    ) {
+        let Some(characteristic) = self.cached_characteristics.get(&characteristic_uuid) else {
+            let _ = responder.send(Err(not_discovered(characteristic_uuid)));
+            return;
+        };
+        // NOTE: CoreBluetooth has no separate signed write type, a write without response to a
+        // characteristic declaring AuthenticatedSignedWrites is signed with the key exchanged
+        // when bonding. The bond isn't exposed either, so writing to a peripheral that was never
+        // bonded is dropped by the peripheral without any error reaching us.
+        let cb_write_type = match write_type {
+            CharacteristicWriteType::WriteWithResponse => CBCharacteristicWriteType::WithResponse,
+            CharacteristicWriteType::WriteWithoutResponse => {
+                CBCharacteristicWriteType::WithoutResponse
+            }
+            CharacteristicWriteType::SignedWriteWithoutResponse => {
+                let properties = unsafe { characteristic.properties() };
+                if !properties.contains(
+                    CBCharacteristicProperties::AuthenticatedSignedWrites,
+                ) {
+                    let _ = responder.send(Err(Error::from_string(
+                        format!("{} doesn't support signed writes", characteristic_uuid),
+                        ErrorType::NotSupported,
+                    )));
+                    return;
+                }
+                CBCharacteristicWriteType::WithoutResponse
+            }
+        };
+        if write_type == CharacteristicWriteType::WriteWithResponse {
+            let claimed = claim(&mut self.write_resolver, characteristic_uuid, "write", |(responder, _, _)| {
+                responder.is_closed()
+            });
+            if let Err(error) = claimed {
+                let _ = responder.send(Err(error));
+                return;
             }
         }
+        let written = data.len();
+        unsafe {
+            self.peripheral.writeValue_forCharacteristic_type(
+                &NSData::from_vec(data),
+                characteristic,
+                cb_write_type,
+            )
+        };
+        // NOTE: CoreBluetooth never confirms a write without response so resolve it straight away
+        match write_type {
+            CharacteristicWriteType::WriteWithResponse => {
+                self.write_resolver
+                    .insert(characteristic_uuid, (responder, written, Instant::now()));
+            }
+            CharacteristicWriteType::WriteWithoutResponse
+            | CharacteristicWriteType::SignedWriteWithoutResponse => {
+                let _ = responder.send(Ok(written));
+            }
+        }
+    }
 
-        // Match events from Corebluetooth delegate
-        Some(delegate_event) = self.corebluetooth_delegate_rx.recv() => {
-            match delegate_event {
-                PeripheralDelegateEvent::DiscoveredServices { services, error } => self.discovered_services(services, error),
-                PeripheralDelegateEvent::DiscoveredCharacteristics { service_uuid, characteristics, error } => self.discovered_characteristics(service_uuid, characteristics, error),
-                PeripheralDelegateEvent::DiscoveredCharacteristicDescriptors { service_uuid, characteristic_uuid, descriptors, error } => self.discovered_descriptors(service_uuid, characteristic_uuid, descriptors, error),
-                PeripheralDelegateEvent::CharacteristicSubscribed {  service_uuid, characteristic_uuid, error} => todo!(),
-                PeripheralDelegateEvent::CharacteristicUnsubscribed {  service_uuid, characteristic_uuid, error} => todo!(),
-                PeripheralDelegateEvent::CharacteristicNotified {  service_uuid, characteristic_uuid, characteristic, error} => todo!(),
-                PeripheralDelegateEvent::CharacteristicWritten {  service_uuid, characteristic_uuid, characteristic, error } => todo!(),
-                PeripheralDelegateEvent::DescriptorNotified {  service_uuid, characteristic_uuid, descriptor_uuid, descriptor, error } => todo!(),
-                PeripheralDelegateEvent::DescriptorWritten {  service_uuid, characteristic_uuid, descriptor_uuid, descriptor, error } => todo!(),
+    async fn characteristic_written(&mut self, characteristic_uuid: Uuid, error: Option<String>) {
+        if let Some((responder, written, started)) = self.write_resolver.remove(&characteristic_uuid) {
+            #[cfg(feature = "metrics")]
+            if error.is_none() {
+                let event = CentralEvent::CharacteristicWriteLatency {
+                    server: self.uuid,
+                    characteristic_uuid,
+                    latency: started.elapsed(),
+                };
+                if let Err(e) = self.central_tx.send(event).await {
+                    log::error!("Error sending central event: {}", e);
+                }
             }
+            #[cfg(not(feature = "metrics"))]
+            let _ = started;
+            resolve(Some(responder), written, error);
+        }
+    }
+
+    fn subscribe(
+        &mut self,
+        characteristic_uuid: Uuid,
+        changes_only: bool,
+        notification_tx: Option<NotificationSender>,
+        responder: oneshot::Sender<Result<()>>,
+    ) {
+        self.subscriptions.insert(
+            characteristic_uuid,
+            Subscription {
+                changes_only,
+                last_value: None,
+                notification_tx,
+                next_sequence: 0,
+            },
+        );
+        self.set_notify(characteristic_uuid, true, responder);
+    }
+
+    // Notifications can also stop without an unsubscribe of ours, e.g. the peripheral disabling
+    // them, in which case the subscription is torn down here. A subscribe already waiting again
+    // keeps its fresh subscription.
+    fn characteristic_unsubscribed(&mut self, characteristic_uuid: Uuid, error: Option<String>) {
+        // Enabling notifications failed, e.g. the characteristic doesn't support them
+        if error.is_some() && self.subscribe_resolver.contains_key(&characteristic_uuid) {
+            self.subscriptions.remove(&characteristic_uuid);
+            self.resolve_subscribe(characteristic_uuid, error);
+            return;
+        }
+        if error.is_none() && !self.subscribe_resolver.contains_key(&characteristic_uuid) {
+            self.subscriptions.remove(&characteristic_uuid);
+        }
+        resolve(self.unsubscribe_resolver.remove(&characteristic_uuid), (), error);
+    }
+
+    fn resolve_subscribe(&mut self, characteristic_uuid: Uuid, error: Option<String>) {
+        let Some(responders) = self.subscribe_resolver.get_mut(&characteristic_uuid) else {
+            return;
+        };
+        let responder = responders.pop_front();
+        if responders.is_empty() {
+            self.subscribe_resolver.remove(&characteristic_uuid);
+        }
+        resolve(responder, (), error);
+    }
+
+    // Dropping the subscription closes its notification stream straight away, before
+    // CoreBluetooth confirms. Also rolls back a subscribe that was never confirmed, its
+    // responder is dropped as the caller already gave up on it.
+    fn unsubscribe(&mut self, characteristic_uuid: Uuid, responder: oneshot::Sender<Result<()>>) {
+        self.subscriptions.remove(&characteristic_uuid);
+        self.subscribe_resolver.remove(&characteristic_uuid);
+        self.set_notify(characteristic_uuid, false, responder);
+    }
+
+    fn set_notify(
+        &mut self,
+        characteristic_uuid: Uuid,
+        enabled: bool,
+        responder: oneshot::Sender<Result<()>>,
+    ) {
+        let Some(characteristic) = self.cached_characteristics.get(&characteristic_uuid) else {
+            let _ = responder.send(Err(not_discovered(characteristic_uuid)));
+            return;
+        };
+        unsafe {
+            self.peripheral
+                .setNotifyValue_forCharacteristic(enabled, characteristic)
+        };
+        if enabled {
+            self.subscribe_resolver
+                .entry(characteristic_uuid)
+                .or_default()
+                .push_back(responder);
+        } else {
+            self.unsubscribe_resolver.insert(characteristic_uuid, responder);
+        }
+    }
+
+    // `key` is the characteristic and descriptor UUID
+    fn read_descriptor(&mut self, key: (Uuid, Uuid), responder: oneshot::Sender<Result<Vec<u8>>>) {
+        let Some(descriptor) = self.cached_descriptors.get(&key) else {
+            let _ = responder.send(Err(not_discovered(key.1)));
+            return;
+        };
+        unsafe { self.peripheral.readValueForDescriptor(descriptor) };
+        self.descriptor_read_resolver.insert(key, responder);
+    }
+
+    fn write_descriptor(
+        &mut self,
+        key: (Uuid, Uuid),
+        data: Vec<u8>,
+        responder: oneshot::Sender<Result<()>>,
+    ) {
+        let Some(descriptor) = self.cached_descriptors.get(&key) else {
+            let _ = responder.send(Err(not_discovered(key.1)));
+            return;
+        };
+        unsafe {
+            self.peripheral
+                .writeValue_forDescriptor(&NSData::from_vec(data), descriptor)
+        };
+        self.descriptor_write_resolver.insert(key, responder);
+    }
+
+    // NOTE: CoreBluetooth reports both read responses and notifications through
+    // didUpdateValueForCharacteristic, a pending read takes the value otherwise it is forwarded
+    // as a notification.
+    async fn characteristic_notified(
+        &mut self,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        value: Payload,
+        error: Option<String>,
+    ) {
+        if self.cancelled_reads.remove(&characteristic_uuid) {
+            log::trace!("Dropping late answer of a cancelled read of {}", characteristic_uuid);
+            return;
+        }
+        if self.discovery_read_in_flight == Some(characteristic_uuid) {
+            self.discovery_read_in_flight = None;
+            if let Some(error) = &error {
+                log::warn!("Reading {} after discovery failed: {}", characteristic_uuid, error);
+            }
+            if let Some(responder) = self.read_resolver.remove(&characteristic_uuid) {
+                resolve(Some(responder), payload_into_vec(value), error);
             }
+            self.read_next_discovered().await;
+            return;
+        }
+        if let Some(responder) = self.read_resolver.remove(&characteristic_uuid) {
+            resolve(Some(responder), payload_into_vec(value), error);
+            return;
+        }
+        if error.is_some() {
+            return;
+        }
+        // A notification racing an unsubscribe has nowhere to go anymore
+        let Some(subscription) = self.subscriptions.get_mut(&characteristic_uuid) else {
+            log::trace!("Dropping notification of unsubscribed {}", characteristic_uuid);
+            return;
+        };
+        if subscription.changes_only {
+            if subscription.last_value.as_ref() == Some(&value) {
+                return;
+            }
+            subscription.last_value = Some(value.clone());
+        }
+        let (sequence, global_sequence) = subscription.take_sequence();
+        if let Some(notification_tx) = &subscription.notification_tx
+            && !notification_tx.send(value.clone()).await
+        {
+            subscription.notification_tx = None;
+        }
+        let event = CentralEvent::CharacteristicNotified {
+            server: self.uuid,
+            service_uuid,
+            characteristic_uuid,
+            value,
+            sequence,
+            global_sequence,
         };
+        if let Err(e) = self.central_tx.send(event).await {
+            log::error!("Error sending central event: {}", e);
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        unsafe { self.peripheral.state() == CBPeripheralState::Connected }
+    }
+
+    // On every advertisement, keeps the last local name seen
+    fn advertised(&mut self, name: Option<String>) {
+        if name.is_some() {
+            self.advertised_name = name;
+        }
+    }
+
+    fn names(&self) -> PeripheralNames {
+        PeripheralNames {
+            cached: unsafe { self.peripheral.name() }.map(|name| name.to_string()),
+            advertised: self.advertised_name.clone(),
+        }
+    }
+
+    // NOTE: A read that is still unanswered when the next tick fires counts as a failure, so a
+    // link that silently went away is detected even though CoreBluetooth never reports an error.
+    fn supervise(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        let Some(supervision) = self.supervision.as_mut() else {
+            return;
+        };
+        if supervision.awaiting_rssi {
+            self.supervision_failed();
+        }
+        if let Some(supervision) = self.supervision.as_mut() {
+            supervision.awaiting_rssi = true;
+        }
+        unsafe { self.peripheral.readRSSI() };
+    }
+
+    fn rssi_read(&mut self, _rssi: i16, error: Option<String>) {
+        let Some(supervision) = self.supervision.as_mut() else {
+            return;
+        };
+        supervision.awaiting_rssi = false;
+        match error {
+            Some(error) => {
+                log::debug!("Supervision RSSI read failed: {}", error);
+                self.supervision_failed();
+            }
+            None => supervision.consecutive_failures = 0,
+        }
+    }
+
+    fn supervision_failed(&mut self) {
+        let Some(supervision) = self.supervision.as_mut() else {
+            return;
+        };
+        supervision.consecutive_failures += 1;
+        if supervision.consecutive_failures < supervision.failure_threshold {
+            return;
+        }
+        log::warn!(
+            "Supervision timeout after {} failed keep-alives, cancelling connection",
+            supervision.consecutive_failures
+        );
+        supervision.consecutive_failures = 0;
+        supervision.awaiting_rssi = false;
+        self.disconnect_reason = Some(DisconnectReason::SupervisionTimeout);
+        unsafe { self.manager.cancelPeripheralConnection(&self.peripheral) };
+    }
+
+    // Before the task ends and drops this peripheral, cancels the connection and fails every
+    // operation still waiting on CoreBluetooth.
+    fn forget(&mut self) {
+        if self.is_connected() {
+            self.disconnect_reason = Some(DisconnectReason::Requested);
+            unsafe { self.manager.cancelPeripheralConnection(&self.peripheral) };
+        }
+        self.fail_pending(
+            &format!("Peripheral {} was forgotten", self.uuid),
+            ErrorType::UnknownPeripheral,
+        );
+    }
+
+    fn fail_pending(&mut self, message: &str, error_type: ErrorType) {
+        let error = Error::from_string(message.to_string(), error_type);
+        fail(self.connect_resolver.take(), &error);
+        fail(self.disconnect_resolver.take(), &error);
+        fail(self.service_discovery_resolver.take(), &error);
+        self.fail_operations(&error);
+    }
+
+    // Fail every pending operation but leave the connection alone. Callbacks for them that still
+    // arrive find no resolver and are dropped, a subscribe that was waiting is dropped with its
+    // subscription so no notification reaches it later.
+    fn cancel_all_operations(&mut self) {
+        self.cancelled_reads.extend(self.read_resolver.keys().copied());
+        self.cancelled_reads.extend(self.discovery_read_in_flight);
+        self.cancel_discovery();
+        for characteristic_uuid in self.subscribe_resolver.keys() {
+            self.subscriptions.remove(characteristic_uuid);
+        }
+        let error = Error::from_string("Operation cancelled".to_string(), ErrorType::Cancelled);
+        self.fail_operations(&error);
+    }
+
+    fn fail_operations(&mut self, error: &Error) {
+        self.discovery_reads = None;
+        self.discovery_read_in_flight = None;
+        for (_, responder) in self.read_resolver.drain() {
+            fail(Some(responder), error);
+        }
+        for (_, (responder, _, _)) in self.write_resolver.drain() {
+            fail(Some(responder), error);
+        }
+        for responder in self.subscribe_resolver.drain().flat_map(|(_, responders)| responders) {
+            fail(Some(responder), error);
+        }
+        for (_, responder) in self.unsubscribe_resolver.drain() {
+            fail(Some(responder), error);
+        }
+        for (_, responder) in self.descriptor_read_resolver.drain() {
+            fail(Some(responder), error);
+        }
+        for (_, responder) in self.descriptor_write_resolver.drain() {
+            fail(Some(responder), error);
+        }
+    }
+
+    // Once didDisconnectPeripheral fires for this peripheral, returns why the disconnect
+    // happened. Anything not initiated by us is a link loss.
+    fn disconnected(&mut self) -> DisconnectReason {
+        self.confirm_disconnect();
+        self.disconnect_reason
+            .take()
+            .unwrap_or(DisconnectReason::LinkLoss)
+    }
+
+    fn confirm_disconnect(&mut self) {
+        // Fulfill the disconnect future, if there is one.
+        // There might not be a future if the device disconnects unexpectedly.
+        resolve(self.disconnect_resolver.take(), (), None);
+
+        // Fail every operation still waiting on the link so no future hangs
+        self.fail_pending("Device disconnected", ErrorType::CoreBluetooth);
+        // Nothing answers over a closed link
+        self.cancelled_reads.clear();
     }
 
     // NOTE: We auto discover services when the Delegate discovered_peripheral is triggered.
     // Don't return the Service until we have finished discovering all the Characteristics and
     // Descriptors
-    fn discovered_services(
+    async fn discovered_services(
         &mut self,
-        services: HashMap<Uuid, Retained<CBService>>,
+        services: Vec<(Uuid, Retained<CBService>)>,
         error: Option<String>,
     ) {
-        for service in services.values() {
+        // Services are discovered on every connection so this is where the MTU of a new
+        // (or re-established) link gets picked up.
+        self.refresh_mtu();
+        if self.discovery_cancelled {
+            return;
+        }
+        if let Some(error) = error {
+            resolve(self.service_discovery_resolver.take(), Vec::new(), Some(error));
+            return;
+        }
+        // NOTE: The cache is keyed by UUID so only the first instance of a service is kept, further
+        // instances are reported instead of silently replacing it.
+        let (discovered, duplicates) = split_duplicate_services(services);
+        for uuid in duplicates {
+            log::warn!("Peripheral {} exposes service {} more than once", self.uuid, uuid);
+            let event = CentralEvent::DuplicateService {
+                server: self.uuid,
+                uuid,
+            };
+            if let Err(e) = self.central_tx.send(event).await {
+                log::error!("Error sending central event: {}", e);
+            }
+        }
+        for (uuid, service) in discovered {
             unsafe {
                 self.peripheral
                     .discoverCharacteristics_forService(None, &service);
                 self.peripheral
                     .discoverIncludedServices_forService(None, &service);
             }
+            self.pending_services.insert(uuid);
+            self.pending_included.insert(uuid);
+            self.cached_services.insert(uuid, service);
+        }
+        self.check_discovered().await;
+    }
+
+    // NOTE: Included services are discovered alongside characteristics for every service. Record
+    // the inclusion relationship on the parent and discover the characteristics of each included
+    // service so they appear in the tree like any other service.
+    async fn discovered_included_services(
+        &mut self,
+        service_uuid: Uuid,
+        included_services: HashMap<Uuid, Retained<CBService>>,
+        error: Option<String>,
+    ) {
+        if self.discovery_cancelled {
+            return;
+        }
+        self.pending_included.remove(&service_uuid);
+        if let Some(error) = error {
+            log::warn!("Included service discovery failed for {}: {}", service_uuid, error);
+            self.check_discovered().await;
+            return;
+        }
+        for (uuid, service) in included_services.iter() {
+            if self.cached_services.contains_key(uuid) {
+                continue;
+            }
+            unsafe {
+                self.peripheral
+                    .discoverCharacteristics_forService(None, service);
+            }
+            self.pending_services.insert(*uuid);
         }
-        self.cached_services.extend(services);
+        self.included_services
+            .insert(service_uuid, included_services.keys().cloned().collect());
+        self.cached_services.extend(included_services);
+        self.check_discovered().await;
     }
 
     // NOTE: We auto discover characteristics when the Delegate
     // didDiscoverCharacteristicsForService is triggered.
     // Don't return the Service until we have finished discovering all the Characteristics and
     // Descriptors
-    fn discovered_characteristics(
+    async fn discovered_characteristics(
         &mut self,
         service: Uuid,
-        characteristics: HashMap<Uuid, Retained<CBCharacteristic>>,
+        characteristics: Vec<(Uuid, Retained<CBCharacteristic>)>,
         error: Option<String>,
     ) {
-        for characteristic in characteristics.values() {
+        if self.discovery_cancelled {
+            return;
+        }
+        if let Some(error) = error {
+            log::warn!("Characteristic discovery failed for {}: {}", service, error);
+        }
+        let characteristic_uuids: Vec<Uuid> = characteristics.iter().map(|(uuid, _)| *uuid).collect();
+        for (_, characteristic) in characteristics.iter() {
             unsafe {
                 self.peripheral
-                    .discoverDescriptorsForCharacteristic(&characteristic)
+                    .discoverDescriptorsForCharacteristic(characteristic)
             };
         }
+        self.pending_services.remove(&service);
+        self.pending_characteristics
+            .extend(characteristic_uuids.iter().cloned());
+        // Declaration order is kept so services() lists characteristics as the peripheral does
+        self.service_characteristics
+            .insert(service, characteristic_uuids);
         self.cached_characteristics.extend(characteristics);
+        self.check_discovered().await;
     }
 
     // NOTE: We auto discover descriptors when the Delegate
     // didDiscoverDescriptorsForCharacteristic is triggered.
     // Don't return the Service until we have finished discovering all the Characteristics and
     // Descriptors.
-    fn discovered_descriptors(
+    async fn discovered_descriptors(
         &mut self,
         service: Uuid,
         characteristic_uuid: Uuid,
-        descriptors: HashMap<Uuid, Retained<CBDescriptor>>,
+        descriptors: Vec<(Uuid, Retained<CBDescriptor>)>,
         error: Option<String>,
     ) {
-        self.cached_descriptors.extend(descriptors);
+        if self.discovery_cancelled {
+            return;
+        }
+        match error {
+            Some(error) => log::warn!(
+                "Descriptor discovery failed for {}/{}: {}",
+                service,
+                characteristic_uuid,
+                error
+            ),
+            None => {
+                let event = CentralEvent::DescriptorsDiscovered {
+                    server: self.uuid,
+                    service_uuid: service,
+                    characteristic_uuid,
+                    descriptors: descriptors
+                        .iter()
+                        .map(|(descriptor_uuid, _)| Descriptor {
+                            uuid: *descriptor_uuid,
+                            service_uuid: service,
+                            characteristic_uuid,
+                            properties: Vec::new(),
+                            permissions: Vec::new(),
+                            value: None,
+                        })
+                        .collect(),
+                };
+                if let Err(e) = self.central_tx.send(event).await {
+                    log::error!("Error sending central event: {}", e);
+                }
+            }
+        }
+        self.pending_characteristics.remove(&characteristic_uuid);
+        self.characteristic_descriptors
+            .insert(characteristic_uuid, descriptors.iter().map(|(uuid, _)| *uuid).collect());
+        self.cached_descriptors.extend(
+            descriptors
+                .into_iter()
+                .map(|(descriptor_uuid, descriptor)| ((characteristic_uuid, descriptor_uuid), descriptor)),
+        );
+        self.check_discovered().await;
     }
 
-    pub fn update_cached_characteristics(
-        &mut self,
-        service_uuid: Uuid,
-        value: HashMap<Uuid, Retained<CBCharacteristic>>,
-    ) {
+    // CoreBluetooth doesn't expose the MTU directly, but the maximum write without response
+    // length is the MTU minus the ATT header.
+    fn refresh_mtu(&self) {
+        let max_write = unsafe {
+            self.peripheral
+                .maximumWriteValueLengthForType(CBCharacteristicWriteType::WithoutResponse)
+        };
+        self.mtu.store(max_write + ATT_HEADER_SIZE, Ordering::Relaxed);
     }
 
-    pub fn update_cached_characteristic_descriptors(
-        &mut self,
-        service_uuid: Uuid,
-        characteristic_uuid: Uuid,
-        descriptors: HashMap<Uuid, Retained<CBDescriptor>>,
-    ) {
+    // Once every service has its characteristics and included services and every characteristic
+    // its descriptors the tree is complete and the waiting discover_services future gets the full Service set.
+    async fn check_discovered(&mut self) {
+        if !self.pending_services.is_empty()
+            || !self.pending_characteristics.is_empty()
+            || !self.pending_included.is_empty()
+        {
+            return;
+        }
+        if self.service_discovery_resolver.is_none() || self.discovery_reads.is_some() {
+            return;
+        }
+        if self.connect_options.read_all_on_discover {
+            let readable = readable_characteristics(
+                self.cached_characteristics
+                    .iter()
+                    .map(|(uuid, characteristic)| (*uuid, unsafe { characteristic.properties() })),
+            );
+            self.discovery_reads = Some(readable);
+            self.read_next_discovered().await;
+            return;
+        }
+        self.discovery_completed().await;
     }
 
-    fn check_discovered(&mut self, service_uuid: Uuid) {
-        // It's time for QUESTIONABLE ASSUMPTIONS.
-        //
-        // For sake of being lazy, we don't want to fire device connection until
-        // we have all of our services and characteristics. We assume that
-        // set_characteristics should be called once for every entry in the
-        // service map. Once that's done, we're filled out enough and can send
-        // back a Connected reply to the waiting future with all of the
-        // characteristic info in it.
-        if self.delegate.is_waiting_for_service(&service_uuid) {
-            panic!("We should still have a future at this point!");
-        }
-        let services = self
-            .services
+    // NOTE: Reads after discovery are issued one at a time, the next only once the previous value
+    // arrived, so a large GATT database doesn't flood the peripheral.
+    async fn read_next_discovered(&mut self) {
+        loop {
+            let next = self
+                .discovery_reads
+                .as_mut()
+                .and_then(|reads| reads.pop_front());
+            let Some(characteristic_uuid) = next else {
+                self.discovery_reads = None;
+                self.discovery_completed().await;
+                return;
+            };
+            // Characteristics gone since discovery are skipped
+            if let Some(characteristic) = self.cached_characteristics.get(&characteristic_uuid) {
+                unsafe { self.peripheral.readValueForCharacteristic(characteristic) };
+                self.discovery_read_in_flight = Some(characteristic_uuid);
+                return;
+            }
+        }
+    }
+
+    async fn discovery_completed(&mut self) {
+        let services = self.services();
+        resolve(self.service_discovery_resolver.take(), services.clone(), None);
+        let event = CentralEvent::ServicesDiscovered {
+            server: self.uuid,
+            services,
+        };
+        if let Err(e) = self.central_tx.send(event).await {
+            log::error!("Error sending central event: {}", e);
+        }
+    }
+
+    fn services(&self) -> Vec<Service> {
+        self.cached_services
             .iter()
             .map(|(&service_uuid, service)| Service {
                 uuid: service_uuid,
-                primary: unsafe { service.cbservice.isPrimary() },
-                characteristics: service
-                    .characteristics
-                    .iter()
-                    .map(|(&characteristic_uuid, characteristic)| {
-                        let descriptors = characteristic
-                            .descriptors
-                            .iter()
-                            .map(|(&descriptor_uuid, _)| Descriptor {
-                                uuid: descriptor_uuid,
-                                service_uuid,
-                                characteristic_uuid,
+                primary: unsafe { service.isPrimary() },
+                included_services: self
+                    .included_services
+                    .get(&service_uuid)
+                    .cloned()
+                    .unwrap_or_default(),
+                characteristics: self
+                    .service_characteristics
+                    .get(&service_uuid)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|characteristic_uuid| {
+                        self.cached_characteristics
+                            .get(characteristic_uuid)
+                            .map(|characteristic| {
+                                self.characteristic(service_uuid, characteristic)
                             })
-                            .collect();
-                        Characteristic {
-                            uuid: characteristic_uuid,
-                            service_uuid,
-                            descriptors,
-                            properties: characteristic.properties,
-                        }
                     })
                     .collect(),
             })
+            .collect()
+    }
+
+    fn characteristic(
+        &self,
+        service_uuid: Uuid,
+        characteristic: &CBCharacteristic,
+    ) -> Characteristic {
+        let characteristic_uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&characteristic.UUID()) };
+        let descriptors = self
+            .characteristic_descriptors
+            .get(&characteristic_uuid)
+            .into_iter()
+            .flatten()
+            .map(|&descriptor_uuid| Descriptor {
+                uuid: descriptor_uuid,
+                service_uuid,
+                characteristic_uuid,
+                properties: Vec::new(),
+                permissions: Vec::new(),
+                value: None,
+            })
             .collect();
+        Characteristic {
+            uuid: characteristic_uuid,
+            service_uuid,
+            properties: CharacteristicProperty::from_cb_properties(unsafe {
+                characteristic.properties()
+            }),
+            permissions: Vec::new(),
+            value: unsafe { characteristic.value() }.map(|value| value.to_vec()),
+            descriptors,
+        }
     }
+}
 
-    pub fn confirm_disconnect(&mut self) {
-        // Fulfill the disconnected future, if there is one.
-        // There might not be a future if the device disconnects unexpectedly.
-        if let Some(future) = self.disconnected_future_state.take() {
-            future.lock().unwrap().set_reply(CoreBluetoothReply::Ok)
-        }
-        self.peripheral.readValueForDescriptor(descriptor);
-
-        // Fulfill all pending futures
-        let error = CoreBluetoothReply::Err(String::from("Device disconnected"));
-        self.services.iter().for_each(|(_, service)| {
-            service
-                .characteristics
-                .iter()
-                .for_each(|(_, characteristic)| {
-                    let CharacteristicInternal {
-                        read_future_state,
-                        write_future_state,
-                        subscribe_future_state,
-                        unsubscribe_future_state,
-                        ..
-                    } = characteristic;
-
-                    let futures = read_future_state
-                        .into_iter()
-                        .chain(write_future_state.into_iter())
-                        .chain(subscribe_future_state.into_iter())
-                        .chain(unsubscribe_future_state.into_iter());
-                    for state in futures {
-                        state.lock().unwrap().set_reply(error.clone());
-                    }
-                });
-        });
+impl Drop for Peripheral {
+    fn drop(&mut self) {
+        // Clear the weak reference before the delegate is released
+        PeripheralDelegate::detach(&self.peripheral);
+    }
+}
+
+async fn supervision_tick(supervision: &mut Option<Supervision>) {
+    match supervision {
+        Some(supervision) => {
+            supervision.ticker.tick().await;
+        }
+        None => future::pending::<()>().await,
+    }
+}
+
+// Characteristics read after discovery with read_all_on_discover, only those that allow reads
+fn readable_characteristics(
+    characteristics: impl Iterator<Item = (Uuid, CBCharacteristicProperties)>,
+) -> VecDeque<Uuid> {
+    characteristics
+        .filter(|(_, properties)| properties.contains(CBCharacteristicProperties::Read))
+        .map(|(uuid, _)| uuid)
+        .collect()
+}
+
+// First instance of every service in discovery order, plus the UUIDs of the further instances
+fn split_duplicate_services<T>(services: Vec<(Uuid, T)>) -> (Vec<(Uuid, T)>, Vec<Uuid>) {
+    let mut seen = HashSet::new();
+    let (first, duplicates): (Vec<_>, Vec<_>) =
+        services.into_iter().partition(|(uuid, _)| seen.insert(*uuid));
+    (first, duplicates.into_iter().map(|(uuid, _)| uuid).collect())
+}
+
+// Reject a read or write with response while another of the same characteristic is in flight,
+// CoreBluetooth's answer doesn't say which one it belongs to. One whose caller timed out
+// (`abandoned`) is removed, its late answer then goes to the next one.
+fn claim<V>(
+    resolver: &mut HashMap<Uuid, V>,
+    characteristic_uuid: Uuid,
+    operation: &str,
+    abandoned: impl Fn(&V) -> bool,
+) -> Result<()> {
+    match resolver.get(&characteristic_uuid) {
+        Some(pending) if !abandoned(pending) => Err(Error::from_string(
+            format!("A {} of {} is already in progress", operation, characteristic_uuid),
+            ErrorType::InProgress,
+        )),
+        Some(_) => {
+            resolver.remove(&characteristic_uuid);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+fn not_discovered(uuid: Uuid) -> Error {
+    Error::from_string(
+        format!("{} has not been discovered on this peripheral", uuid),
+        ErrorType::NotFound,
+    )
+}
+
+// Resolve a pending responder with the delegate outcome, a missing responder means nobody is
+// waiting anymore (e.g. the caller timed out) so the result is dropped.
+fn fail<T>(responder: Option<oneshot::Sender<Result<T>>>, error: &Error) {
+    if let Some(responder) = responder {
+        let _ = responder.send(Err(error.clone()));
+    }
+}
+
+fn resolve<T>(responder: Option<oneshot::Sender<Result<T>>>, value: T, error: Option<String>) {
+    let Some(responder) = responder else {
+        return;
+    };
+    let result = match error {
+        Some(error) => Err(Error::from_string(error, ErrorType::CoreBluetooth)),
+        None => Ok(value),
+    };
+    let _ = responder.send(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription() -> Subscription {
+        Subscription {
+            changes_only: false,
+            last_value: None,
+            notification_tx: None,
+            next_sequence: 0,
+        }
+    }
+
+    #[test]
+    fn sequence_numbers_increase_across_a_burst() {
+        let (mut first, mut second) = (subscription(), subscription());
+        let mut globals = Vec::new();
+        for expected in 0..100 {
+            for subscription in [&mut first, &mut second] {
+                let (sequence, global) = subscription.take_sequence();
+                assert_eq!(sequence, expected);
+                globals.push(global);
+            }
+        }
+        assert!(globals.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn only_readable_characteristics_are_read_after_discovery() {
+        let (readable, write_only, notify_read) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        let characteristics = [
+            (readable, CBCharacteristicProperties::Read),
+            (write_only, CBCharacteristicProperties::Write),
+            (notify_read, CBCharacteristicProperties::Notify | CBCharacteristicProperties::Read),
+        ];
+        let reads = readable_characteristics(characteristics.into_iter());
+        assert_eq!(reads, VecDeque::from([readable, notify_read]));
+    }
+
+    fn is_closed<T>(responder: &oneshot::Sender<T>) -> bool {
+        responder.is_closed()
+    }
+
+    #[test]
+    fn concurrent_read_is_rejected() {
+        let characteristic = Uuid::from_u128(1);
+        let mut read_resolver = HashMap::new();
+        let (responder, _receiver) = oneshot::channel::<Result<Vec<u8>>>();
+        read_resolver.insert(characteristic, responder);
+        let error = claim(&mut read_resolver, characteristic, "read", is_closed).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InProgress);
+        assert!(claim(&mut read_resolver, Uuid::from_u128(2), "read", is_closed).is_ok());
+    }
+
+    #[test]
+    fn timed_out_read_is_removed() {
+        let characteristic = Uuid::from_u128(1);
+        let mut read_resolver = HashMap::new();
+        let (responder, receiver) = oneshot::channel::<Result<Vec<u8>>>();
+        read_resolver.insert(characteristic, responder);
+        drop(receiver);
+        assert!(claim(&mut read_resolver, characteristic, "read", is_closed).is_ok());
+        assert!(read_resolver.is_empty());
+    }
+
+    #[test]
+    fn concurrent_write_with_response_is_rejected() {
+        let characteristic = Uuid::from_u128(1);
+        let mut write_resolver = HashMap::new();
+        let (responder, receiver) = oneshot::channel::<Result<usize>>();
+        write_resolver.insert(characteristic, (responder, 4, Instant::now()));
+        let abandoned = |(responder, _, _): &(oneshot::Sender<Result<usize>>, usize, Instant)| {
+            responder.is_closed()
+        };
+        let error = claim(&mut write_resolver, characteristic, "write", abandoned).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InProgress);
+        drop(receiver);
+        assert!(claim(&mut write_resolver, characteristic, "write", abandoned).is_ok());
+        assert!(write_resolver.is_empty());
+    }
+    #[test]
+    fn only_the_first_instance_of_a_service_is_kept() {
+        let (heart_rate, battery) = (Uuid::from_u128(0x180D), Uuid::from_u128(0x180F));
+        let services = vec![(heart_rate, 1), (battery, 2), (heart_rate, 3), (heart_rate, 4)];
+        let (discovered, duplicates) = split_duplicate_services(services);
+        assert_eq!(discovered, vec![(heart_rate, 1), (battery, 2)]);
+        assert_eq!(duplicates, vec![heart_rate, heart_rate]);
     }
 }
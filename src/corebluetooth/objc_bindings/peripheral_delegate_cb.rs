@@ -1,19 +1,22 @@
+use crate::api::central_event::Payload;
 use crate::corebluetooth::objc_bindings::{
-    AdvertisementResolver, ServiceResolver,
+    central_manager_delegate_cb::{
+        characteristic_debug, descriptor_debug, localized_description, peripheral_debug,
+        service_debug,
+    },
     mac_extensions_cb::{self},
 };
 
 use futures::executor;
 use log::trace;
-use objc2::{AnyThread, define_class, msg_send};
+use objc2::{AnyThread, define_class, msg_send, runtime::ProtocolObject};
 use objc2::{DeclaredClass, rc::Retained};
 use objc2_core_bluetooth::{
     CBCharacteristic, CBDescriptor, CBPeripheral, CBPeripheralDelegate, CBService,
 };
-use objc2_foundation::{NSError, NSNumber, NSObject, NSObjectProtocol};
-use std::sync::Arc;
+use objc2_foundation::{NSArray, NSData, NSError, NSNumber, NSObject, NSObjectProtocol};
 use std::{collections::HashMap, fmt::Debug};
-use tokio::sync::{Mutex, mpsc::Sender};
+use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
 // Instance Variables that are stored within the ObjC class allowing communication between Rust
@@ -21,8 +24,6 @@ use uuid::Uuid;
 #[derive(Debug)]
 pub struct IVars {
     pub sender: Sender<PeripheralDelegateEvent>,
-    pub services_resolver: Arc<Mutex<ServiceResolver>>,
-    pub advertisement_resolver: Arc<Mutex<AdvertisementResolver>>,
 }
 
 define_class!(
@@ -48,22 +49,36 @@ define_class!(
                 localized_description(error)
             );
             let services = unsafe { peripheral.services() }.unwrap_or_default();
-            let mut service_map = HashMap::new();
-            for s in services {
-                // go ahead and ask for characteristics and other services
-                unsafe {
-                    let uuid = mac_extensions_cb::cbuuid_to_uuid(&s.UUID());
-                    service_map.insert(uuid, s);
-                }
-            }
-            let peripheral_uuid =
-                mac_extensions_cb::nsuuid_to_uuid(unsafe { &peripheral.identifier() });
+            // Kept as a list, a peripheral may expose several instances of the same service
+            let services = services
+                .into_iter()
+                .map(|s| (unsafe { mac_extensions_cb::cbuuid_to_uuid(&s.UUID()) }, s))
+                .collect();
             self.send_event(PeripheralDelegateEvent::DiscoveredServices {
-                services: service_map,
+                services,
                 error: error.map(|e| e.localizedDescription().to_string()),
             });
         }
 
+        #[unsafe(method(peripheral:didModifyServices:))]
+        fn delegate_peripheral_didmodifyservices(
+            &self,
+            peripheral: &CBPeripheral,
+            invalidated_services: &NSArray<CBService>,
+        ) {
+            trace!(
+                "delegate_peripheral_didmodifyservices {}",
+                peripheral_debug(peripheral)
+            );
+            let invalidated_services = invalidated_services
+                .iter()
+                .map(|s| unsafe { mac_extensions_cb::cbuuid_to_uuid(&s.UUID()) })
+                .collect();
+            self.send_event(PeripheralDelegateEvent::ServicesModified {
+                invalidated_services,
+            });
+        }
+
         #[unsafe(method(peripheral:didDiscoverIncludedServicesForService:error:))]
         fn delegate_peripheral_diddiscoverincludedservicesforservice_error(
             &self,
@@ -77,13 +92,18 @@ define_class!(
                 service_debug(service),
                 localized_description(error)
             );
-            // TODO: make ths a oneshot and pull this logic into the peripheral
-            if error.is_none() {
-                let includes = unsafe { service.includedServices() }.unwrap_or_default();
-                for s in includes {
-                    unsafe { peripheral.discoverCharacteristics_forService(None, &s) };
-                }
+            let mut included_services = HashMap::new();
+            let includes = unsafe { service.includedServices() }.unwrap_or_default();
+            for s in includes {
+                let uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&s.UUID()) };
+                included_services.insert(uuid, s);
             }
+            let service_uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) };
+            self.send_event(PeripheralDelegateEvent::DiscoveredIncludedServices {
+                service_uuid,
+                included_services,
+                error: error.map(|e| e.localizedDescription().to_string()),
+            });
         }
 
         #[unsafe(method(peripheral:didDiscoverCharacteristicsForService:error:))]
@@ -99,17 +119,13 @@ define_class!(
                 service_debug(service),
                 localized_description(error)
             );
-            let mut characteristics = HashMap::new();
+            // Kept in the order CoreBluetooth reports them, which is the GATT declaration order
+            let mut characteristics = Vec::new();
             let chars = unsafe { service.characteristics() }.unwrap_or_default();
             for c in chars {
-                unsafe {
-                    // Create the mp entry we'll need to export.
-                    let uuid = mac_extensions_cb::cbuuid_to_uuid(unsafe { &c.UUID() });
-                    characteristics.insert(uuid, c);
-                }
+                let uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&c.UUID()) };
+                characteristics.push((uuid, c));
             }
-            let peripheral_uuid =
-                mac_extensions_cb::nsuuid_to_uuid(unsafe { &peripheral.identifier() });
             unsafe {
                 let service_uuid = mac_extensions_cb::cbuuid_to_uuid(&service.UUID());
                 self.send_event(PeripheralDelegateEvent::DiscoveredCharacteristics {
@@ -134,11 +150,11 @@ define_class!(
                 localized_description(error)
             );
 
-            let mut descriptors = HashMap::new();
+            let mut descriptors = Vec::new();
             let descs = unsafe { characteristic.descriptors() }.unwrap_or_default();
             for d in descs {
                 let uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&d.UUID()) };
-                descriptors.insert(uuid, d);
+                descriptors.push((uuid, d));
             }
             let service = unsafe { characteristic.service() }.unwrap();
             let service_uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) };
@@ -168,17 +184,15 @@ define_class!(
                 localized_description(error)
             );
 
-            // TODO: make this a oneshot and pull logic into peripheral
-            if error.is_none() {
-                let service = unsafe { characteristic.service() }.unwrap();
-                self.send_event(PeripheralDelegateEvent::CharacteristicNotified {
-                    peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
-                    service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
-                    characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
-                    data: get_characteristic_value(characteristic),
-                });
-                // Notify BluetoothGATTCharacteristic::read_value that read was successful.
-            }
+            let service = unsafe { characteristic.service() }.unwrap();
+            self.send_event(PeripheralDelegateEvent::CharacteristicNotified {
+                service_uuid: unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) },
+                characteristic_uuid: unsafe {
+                    mac_extensions_cb::cbuuid_to_uuid(&characteristic.UUID())
+                },
+                value: get_characteristic_value(characteristic),
+                error: error.map(|e| e.localizedDescription().to_string()),
+            });
         }
 
         #[unsafe(method(peripheral:didWriteValueForCharacteristic:error:))]
@@ -195,15 +209,14 @@ define_class!(
                 localized_description(error)
             );
 
-            // TODO: make this a oneshot and pull logic into peripheral
-            if error.is_none() {
-                let service = unsafe { characteristic.service() }.unwrap();
-                self.send_event(PeripheralDelegateEvent::CharacteristicWritten {
-                    peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
-                    service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
-                    characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
-                });
-            }
+            let service = unsafe { characteristic.service() }.unwrap();
+            self.send_event(PeripheralDelegateEvent::CharacteristicWritten {
+                service_uuid: unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) },
+                characteristic_uuid: unsafe {
+                    mac_extensions_cb::cbuuid_to_uuid(&characteristic.UUID())
+                },
+                error: error.map(|e| e.localizedDescription().to_string()),
+            });
         }
 
         #[unsafe(method(peripheral:didUpdateNotificationStateForCharacteristic:error:))]
@@ -211,27 +224,30 @@ define_class!(
             &self,
             peripheral: &CBPeripheral,
             characteristic: &CBCharacteristic,
-            _error: Option<&NSError>,
+            error: Option<&NSError>,
         ) {
-            trace!("delegate_peripheral_didupdatenotificationstateforcharacteristic_error");
-            // TODO check for error here
-            //
-            // TODO: make this a oneshot and pull logic into peripheral
-            let peripheral_uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
+            trace!(
+                "delegate_peripheral_didupdatenotificationstateforcharacteristic_error {} {} {}",
+                peripheral_debug(peripheral),
+                characteristic_debug(characteristic),
+                localized_description(error)
+            );
             let service = unsafe { characteristic.service() }.unwrap();
-            let service_uuid = cbuuid_to_uuid(unsafe { &service.UUID() });
-            let characteristic_uuid = cbuuid_to_uuid(unsafe { &characteristic.UUID() });
+            let service_uuid = unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) };
+            let characteristic_uuid =
+                unsafe { mac_extensions_cb::cbuuid_to_uuid(&characteristic.UUID()) };
+            let error = error.map(|e| e.localizedDescription().to_string());
             if unsafe { characteristic.isNotifying() } {
                 self.send_event(PeripheralDelegateEvent::CharacteristicSubscribed {
-                    peripheral_uuid,
                     service_uuid,
                     characteristic_uuid,
+                    error,
                 });
             } else {
                 self.send_event(PeripheralDelegateEvent::CharacteristicUnsubscribed {
-                    peripheral_uuid,
                     service_uuid,
                     characteristic_uuid,
+                    error,
                 });
             }
         }
@@ -240,7 +256,7 @@ define_class!(
         fn delegate_peripheral_didreadrssi_error(
             &self,
             peripheral: &CBPeripheral,
-            _rssi: &NSNumber,
+            rssi: &NSNumber,
             error: Option<&NSError>,
         ) {
             trace!(
@@ -248,8 +264,10 @@ define_class!(
                 peripheral_debug(peripheral)
             );
 
-            // TODO: make this a oneshot and pull logic into peripheral
-            if error.is_none() {}
+            self.send_event(PeripheralDelegateEvent::RssiRead {
+                rssi: rssi.as_i16(),
+                error: error.map(|e| e.localizedDescription().to_string()),
+            });
         }
 
         #[unsafe(method(peripheral:didUpdateValueForDescriptor:error:))]
@@ -266,19 +284,17 @@ define_class!(
                 localized_description(error)
             );
 
-            // TODO: make this a oneshot and pull logic into peripheral
-            if error.is_none() {
-                let characteristic = unsafe { descriptor.characteristic() }.unwrap();
-                let service = unsafe { characteristic.service() }.unwrap();
-                self.send_event(PeripheralDelegateEvent::DescriptorNotified {
-                    peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
-                    service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
-                    characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
-                    descriptor_uuid: cbuuid_to_uuid(unsafe { &descriptor.UUID() }),
-                    data: get_descriptor_value(&descriptor),
-                });
-                // Notify BluetoothGATTCharacteristic::read_value that read was successful.
-            }
+            let characteristic = unsafe { descriptor.characteristic() }.unwrap();
+            let service = unsafe { characteristic.service() }.unwrap();
+            self.send_event(PeripheralDelegateEvent::DescriptorNotified {
+                service_uuid: unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) },
+                characteristic_uuid: unsafe {
+                    mac_extensions_cb::cbuuid_to_uuid(&characteristic.UUID())
+                },
+                descriptor_uuid: unsafe { mac_extensions_cb::cbuuid_to_uuid(&descriptor.UUID()) },
+                value: get_descriptor_value(descriptor),
+                error: error.map(|e| e.localizedDescription().to_string()),
+            });
         }
 
         #[unsafe(method(peripheral:didWriteValueForDescriptor:error:))]
@@ -295,17 +311,16 @@ define_class!(
                 localized_description(error)
             );
 
-            // TODO: make this a oneshot and pull logic into peripheral
-            if error.is_none() {
-                let characteristic = unsafe { descriptor.characteristic() }.unwrap();
-                let service = unsafe { characteristic.service() }.unwrap();
-                self.send_event(PeripheralDelegateEvent::DescriptorWritten {
-                    peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
-                    service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
-                    characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
-                    descriptor_uuid: cbuuid_to_uuid(unsafe { &descriptor.UUID() }),
-                });
-            }
+            let characteristic = unsafe { descriptor.characteristic() }.unwrap();
+            let service = unsafe { characteristic.service() }.unwrap();
+            self.send_event(PeripheralDelegateEvent::DescriptorWritten {
+                service_uuid: unsafe { mac_extensions_cb::cbuuid_to_uuid(&service.UUID()) },
+                characteristic_uuid: unsafe {
+                    mac_extensions_cb::cbuuid_to_uuid(&characteristic.UUID())
+                },
+                descriptor_uuid: unsafe { mac_extensions_cb::cbuuid_to_uuid(&descriptor.UUID()) },
+                error: error.map(|e| e.localizedDescription().to_string()),
+            });
         }
     }
 );
@@ -314,12 +329,21 @@ impl PeripheralDelegate {
     pub fn new(sender: Sender<PeripheralDelegateEvent>) -> Retained<PeripheralDelegate> {
         let this = PeripheralDelegate::alloc().set_ivars(IVars {
             sender,
-            services_resolver: Arc::new(Mutex::new(ServiceResolver::new())),
-            advertisement_resolver: Arc::new(Mutex::new(AdvertisementResolver::new())),
         });
         unsafe { msg_send![super(this), init] }
     }
 
+    // NOTE: CBPeripheral only holds a weak reference to its delegate, once the last Retained is
+    // dropped callbacks silently stop. The caller must keep the Retained<PeripheralDelegate> alive
+    // for as long as the peripheral is in use, see peripheral_cb::Peripheral which owns both.
+    pub fn attach(&self, peripheral: &CBPeripheral) {
+        unsafe { peripheral.setDelegate(Some(ProtocolObject::from_ref(self))) };
+    }
+
+    pub fn detach(peripheral: &CBPeripheral) {
+        unsafe { peripheral.setDelegate(None) };
+    }
+
     fn send_event(&self, event: PeripheralDelegateEvent) {
         let sender = self.ivars().sender.clone();
         executor::block_on(async {
@@ -330,20 +354,45 @@ impl PeripheralDelegate {
     }
 }
 
+// Notifications are the hot path so the value is handed over without an intermediate copy
+fn get_characteristic_value(characteristic: &CBCharacteristic) -> Payload {
+    unsafe { characteristic.value() }
+        .map(mac_extensions_cb::nsdata_to_payload)
+        .unwrap_or_default()
+}
+
+// NOTE: Descriptor values are typed by CoreBluetooth (NSData, NSString or NSNumber depending on
+// the descriptor), only raw NSData values are passed through.
+fn get_descriptor_value(descriptor: &CBDescriptor) -> Vec<u8> {
+    unsafe { descriptor.value() }
+        .and_then(|value| value.downcast_ref::<NSData>().map(|data| data.to_vec()))
+        .unwrap_or_default()
+}
+
+// The service of a characteristic event isn't read, the backend keys on the characteristic
+#[allow(dead_code)]
 pub enum PeripheralDelegateEvent {
     DiscoveredServices {
-        services: HashMap<Uuid, Retained<CBService>>,
+        services: Vec<(Uuid, Retained<CBService>)>,
+        error: Option<String>,
+    },
+    ServicesModified {
+        invalidated_services: Vec<Uuid>,
+    },
+    DiscoveredIncludedServices {
+        service_uuid: Uuid,
+        included_services: HashMap<Uuid, Retained<CBService>>,
         error: Option<String>,
     },
     DiscoveredCharacteristics {
         service_uuid: Uuid,
-        characteristics: HashMap<Uuid, Retained<CBCharacteristic>>,
+        characteristics: Vec<(Uuid, Retained<CBCharacteristic>)>,
         error: Option<String>,
     },
     DiscoveredCharacteristicDescriptors {
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
-        descriptors: HashMap<Uuid, Retained<CBDescriptor>>,
+        descriptors: Vec<(Uuid, Retained<CBDescriptor>)>,
         error: Option<String>,
     },
     CharacteristicSubscribed {
@@ -359,27 +408,29 @@ pub enum PeripheralDelegateEvent {
     CharacteristicNotified {
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
-        characteristic: Retained<CBCharacteristic>,
+        value: Payload,
         error: Option<String>,
     },
     CharacteristicWritten {
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
-        characteristic: Retained<CBCharacteristic>,
         error: Option<String>,
     },
     DescriptorNotified {
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
         descriptor_uuid: Uuid,
-        descriptor: Retained<CBDescriptor>,
+        value: Vec<u8>,
+        error: Option<String>,
+    },
+    RssiRead {
+        rssi: i16,
         error: Option<String>,
     },
     DescriptorWritten {
         service_uuid: Uuid,
         characteristic_uuid: Uuid,
         descriptor_uuid: Uuid,
-        descriptor: Retained<CBDescriptor>,
         error: Option<String>,
     },
 }
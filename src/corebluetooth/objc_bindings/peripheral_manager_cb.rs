@@ -1,71 +1,151 @@
+use super::{build_runtime, queue_label};
 use super::mac_utils_cb;
 use super::peripheral_manager_delegate_cb::PeripheralManagerDelegate;
-use super::{characteristic_utils_cb::parse_characteristic, mac_extensions_cb::uuid_to_cbuuid};
-use crate::Error;
-use crate::api::peripheral_event::PeripheralEvent;
+use super::{
+    characteristic_utils_cb::parse_characteristic,
+    mac_extensions_cb::{UuidExtension, uuid_to_cbuuid},
+};
+use crate::{Error, ErrorType};
+use crate::api::advertisement::AdvertisementData;
+use crate::api::central_event::CentralState;
+use crate::api::characteristic::{ATT_HEADER_SIZE, DEFAULT_ATT_MTU};
+use crate::api::config::Config;
+use crate::api::peripheral_event::{
+    PeripheralEvent, PeripheralRequest, PeripheralRequestResponse, QueuedWrite, ReadHandler,
+    RequestHandlers, RequestResponse, WriteHandler, WriteRequestResponse, WriteValidator,
+};
 use crate::api::service::Service;
 use crate::corebluetooth::objc_bindings::peripheral_manager_delegate_cb::PeripheralManagerDelegateEvent;
 use crate::corebluetooth::peripheral_manager::PeripheralManagerCommand;
 use objc2::{AnyThread, msg_send};
 use objc2::{rc::Retained, runtime::AnyObject};
 use objc2_core_bluetooth::{
-    CBAdvertisementDataLocalNameKey, CBAdvertisementDataServiceUUIDsKey, CBCharacteristic,
-    CBManager, CBManagerAuthorization, CBManagerState, CBMutableCharacteristic, CBMutableService,
+    CBATTError, CBATTRequest, CBAdvertisementDataLocalNameKey, CBAdvertisementDataServiceUUIDsKey, CBCharacteristic,
+    CBManagerState, CBMutableCharacteristic, CBMutableService,
     CBPeripheralManager,
 };
 use objc2_foundation::{NSArray, NSData, NSDictionary, NSString};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::CString;
-use std::sync::OnceLock;
+use std::future;
 use std::thread;
-use tokio::runtime;
+use std::time::Duration;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, watch};
+use tokio::time::{self, Instant};
 use uuid::Uuid;
 
-static PERIPHERAL_THREAD: OnceLock<()> = OnceLock::new();
+// How long a start_advertising waits for didStartAdvertising
+const ADVERTISING_START_TIMEOUT: Duration = Duration::from_secs(5);
 
-// Handle Peripheral Manager and all communication in a separate thread
+// Handle Peripheral Manager and all communication in a separate thread. Every Peripheral gets its
+// own thread, which ends once that Peripheral is dropped.
 pub fn run_peripheral_thread(
     sender: Sender<PeripheralEvent>,
     listener: Receiver<PeripheralManagerCommand>,
+    state_tx: watch::Sender<CentralState>,
+    config: Config,
 ) {
-    PERIPHERAL_THREAD.get_or_init(|| {
-        thread::spawn(move || {
-            let runtime = runtime::Builder::new_current_thread().enable_time().build();
-            if runtime.is_err() {
-                log::error!("Failed to create runtime");
-                return;
-            }
-            runtime.unwrap().block_on(async move {
-                let mut peripheral_manager = PeripheralManager::new(sender, listener);
-                loop {
-                    peripheral_manager.handle_event().await;
-                }
-            })
-        });
+    thread::spawn(move || {
+        let runtime = build_runtime(&config.runtime);
+        if runtime.is_err() {
+            log::error!("Failed to create runtime");
+            return;
+        }
+        runtime.unwrap().block_on(async move {
+            let mut peripheral_manager =
+                PeripheralManager::new(sender, listener, state_tx, &config.peripheral_queue);
+            while peripheral_manager.handle_event().await {}
+        })
     });
 }
 
-#[derive(Debug)]
 struct PeripheralManager {
     cb_peripheral_manager: Retained<CBPeripheralManager>,
     peripheral_delegate: Retained<PeripheralManagerDelegate>,
     cached_characteristics: HashMap<Uuid, Retained<CBMutableCharacteristic>>,
+    // Last value set for each characteristic, handed to centrals as they subscribe
+    characteristic_values: HashMap<Uuid, Vec<u8>>,
+    // maximumUpdateValueLength of each subscribed central, keyed by central and characteristic
+    subscribers: HashMap<(String, Uuid), usize>,
     peripheral_tx: Sender<PeripheralEvent>,
+    // Latest manager state, read by wait_until_powered_on
+    state_tx: watch::Sender<CentralState>,
     corebluetooth_delegate_rx: Receiver<PeripheralManagerDelegateEvent>,
     manager_command_rx: Receiver<PeripheralManagerCommand>,
+    // ATT requests waiting on the app to respond, keyed by the id handed out in the event
+    pending_requests: HashMap<u64, Retained<CBATTRequest>>,
+    next_request_id: u64,
+    // Local name and services of the last start_advertising, kept to re-advertise on a rename
+    local_name: Option<String>,
+    advertised_uuids: Vec<Uuid>,
+    // Characteristics whose reads are answered here instead of through events
+    read_handlers: HashMap<Uuid, ReadHandler>,
+    write_handlers: HashMap<Uuid, WriteHandler>,
+    write_validators: HashMap<Uuid, WriteValidator>,
+    // Set from startAdvertising until didStartAdvertising, the command loop keeps running
+    // meanwhile so a stop can still come in
+    advertising_start: Option<AdvertisingStart>,
+    // Notifications CoreBluetooth had no room for, sent in order on ReadyToUpdateSubscribers
+    pending_updates: VecDeque<PendingUpdate>,
+}
+
+#[derive(Debug)]
+struct PendingUpdate {
+    characteristic: Uuid,
+    value: Vec<u8>,
+    responder: oneshot::Sender<Result<(), Error>>,
+}
+
+// A start_advertising waiting on the delegate
+#[derive(Debug)]
+struct AdvertisingStart {
+    responder: oneshot::Sender<Result<(), Error>>,
+    deadline: Instant,
+}
+
+impl AdvertisingStart {
+    fn new(responder: oneshot::Sender<Result<(), Error>>) -> Self {
+        AdvertisingStart {
+            responder,
+            deadline: Instant::now() + ADVERTISING_START_TIMEOUT,
+        }
+    }
+
+    fn resolve(self, error: Option<String>) {
+        let result = match error {
+            Some(error) => Err(Error::from_string(error, ErrorType::CoreBluetooth)),
+            None => Ok(()),
+        };
+        let _ = self.responder.send(result);
+    }
+
+    fn cancel(self) {
+        let _ = self.responder.send(Err(Error::from_string(
+            "Advertising stopped before it started".to_string(),
+            ErrorType::Cancelled,
+        )));
+    }
+
+    fn expire(self) {
+        let _ = self.responder.send(Err(Error::from_string(
+            "Timeout waiting for advertising to start".to_string(),
+            ErrorType::Timeout,
+        )));
+    }
 }
 
 impl PeripheralManager {
     fn new(
         peripheral_tx: Sender<PeripheralEvent>,
         manager_rx: Receiver<PeripheralManagerCommand>,
+        state_tx: watch::Sender<CentralState>,
+        queue: &str,
     ) -> Self {
         let (delegate_tx, delegate_rx) = mpsc::channel::<PeripheralManagerDelegateEvent>(256);
         let delegate: Retained<PeripheralManagerDelegate> =
             PeripheralManagerDelegate::new(delegate_tx);
-        let label: CString = CString::new("CBqueue").unwrap();
+        let label: CString = queue_label(queue);
         let queue: *mut std::ffi::c_void = unsafe {
             mac_utils_cb::dispatch_queue_create(label.as_ptr(), mac_utils_cb::DISPATCH_QUEUE_SERIAL)
         };
@@ -79,16 +159,32 @@ impl PeripheralManager {
             cb_peripheral_manager: peripheral_manager,
             peripheral_delegate: delegate,
             peripheral_tx,
+            state_tx,
             cached_characteristics: HashMap::new(),
+            characteristic_values: HashMap::new(),
+            subscribers: HashMap::new(),
             corebluetooth_delegate_rx: delegate_rx,
+            pending_requests: HashMap::new(),
+            next_request_id: 0,
+            local_name: None,
+            advertised_uuids: Vec::new(),
+            read_handlers: HashMap::new(),
+            write_handlers: HashMap::new(),
+            write_validators: HashMap::new(),
+            advertising_start: None,
+            pending_updates: VecDeque::new(),
         }
     }
 
-    async fn handle_event(&mut self) {
+    // False once the Peripheral is dropped, which ends the manager loop
+    async fn handle_event(&mut self) -> bool {
         tokio::select! {
 
         // Match events from above
-        Some(manager_command) = self.manager_command_rx.recv() => {
+        manager_command = self.manager_command_rx.recv() => {
+            let Some(manager_command) = manager_command else {
+                return false;
+            };
             match manager_command {
                 PeripheralManagerCommand::IsPowered { responder } => {
                     let _ = responder.send(Ok(self.is_powered()));
@@ -101,20 +197,44 @@ impl PeripheralManager {
                     uuids,
                     responder,
                 } => {
-                    let _ = responder.send(self.start_advertising(&name, &uuids).await);
+                    let name = name.or_else(|| self.local_name.clone());
+                    self.start_advertising(name, uuids, responder)
+                }
+                PeripheralManagerCommand::AdvertiseBeacon { data, responder } => {
+                    self.advertise_beacon(data, responder)
                 }
                 PeripheralManagerCommand::StopAdvertising { responder } => {
-                    let _ = responder.send(Ok(self.stop_advertising()));
+                    self.stop_advertising().await;
+                    let _ = responder.send(Ok(()));
+                }
+                // Stop advertising before the loop ends and the CBPeripheralManager is released
+                PeripheralManagerCommand::Shutdown => {
+                    self.stop_advertising().await;
+                    return false;
+                }
+                PeripheralManagerCommand::LocalName { responder } => {
+                    let _ = responder.send(Ok(self.local_name.clone()));
                 }
-                PeripheralManagerCommand::AddService { service, responder } => {
-                    let _ = responder.send(self.add_service(&service).await);
+                PeripheralManagerCommand::SetLocalName { name, responder } => {
+                    self.set_local_name(name, responder).await
+                }
+                PeripheralManagerCommand::AddService { service, handlers, responder } => {
+                    let _ = responder.send(self.add_service(&service, handlers).await);
                 }
                 PeripheralManagerCommand::UpdateCharacteristic {
                     characteristic,
                     value,
                     responder,
+                } => self.update_characteristic(characteristic, value, responder),
+                PeripheralManagerCommand::MaxNotifyLength { responder } => {
+                    let _ = responder.send(Ok(self.max_notify_length()));
+                }
+                PeripheralManagerCommand::RespondToRequest {
+                    id,
+                    response,
+                    responder,
                 } => {
-                    let _ = responder.send(self.update_characteristic(characteristic, value).await);
+                    let _ = responder.send(self.respond_to_request(id, response));
                 }
             }
         }
@@ -122,89 +242,395 @@ impl PeripheralManager {
         // Match events from Corebluetooth delegate
         Some(delegate_event) = self.corebluetooth_delegate_rx.recv() => {
             match delegate_event {
+                PeripheralManagerDelegateEvent::StateUpdate { state } => self.state_updated(state).await,
+                PeripheralManagerDelegateEvent::ReadyToUpdateSubscribers => self.send_pending_updates(),
+                PeripheralManagerDelegateEvent::AdvertisingStarted { error } => {
+                    if let Some(start) = self.advertising_start.take() {
+                        start.resolve(error.clone());
+                    }
+                    self.advertising_state_changed(error.is_none(), error).await
                 }
+                PeripheralManagerDelegateEvent::ReadRequest { request } => self.read_requested(request).await,
+                PeripheralManagerDelegateEvent::WriteRequests { requests } => self.write_requested(requests).await,
+                PeripheralManagerDelegateEvent::SubscriptionUpdate { request, subscribed } => self.subscription_updated(request, subscribed).await,
+                }
+            }
+
+        // Give up on a start the delegate never confirmed
+        _ = until(self.advertising_start.as_ref().map(|start| start.deadline)) => {
+            if let Some(start) = self.advertising_start.take() {
+                start.expire();
+            }
+        }
+        };
+        true
+    }
+
+    async fn read_requested(&mut self, request: Retained<CBATTRequest>) {
+        let Some(peripheral_request) = peripheral_request(&request) else {
+            self.reject_request(&request);
+            return;
+        };
+        let offset = unsafe { request.offset() } as u64;
+        if let Some(handler) = self.read_handlers.get_mut(&peripheral_request.characteristic) {
+            let response = handler(offset, &peripheral_request);
+            self.respond(&request, response.into());
+            return;
+        }
+        let id = self.track_request(request.clone());
+        let event = PeripheralEvent::ReadRequest {
+            id,
+            request: peripheral_request,
+            offset,
+        };
+        self.send_event(id, event).await;
+    }
+
+    // CoreBluetooth hands over every part of a long or reliable write at once and expects one
+    // answer for all of them, given on the first request
+    async fn write_requested(&mut self, requests: Vec<Retained<CBATTRequest>>) {
+        let Some(first) = requests.first().cloned() else {
+            return;
+        };
+        let mut writes = Vec::with_capacity(requests.len());
+        for request in requests.iter() {
+            // A part that can't be described fails the whole write
+            let Some(peripheral_request) = peripheral_request(request) else {
+                self.reject_request(&first);
+                return;
+            };
+            writes.push(QueuedWrite {
+                request: peripheral_request,
+                value: unsafe { request.value() }
+                    .map(|data| data.to_vec())
+                    .unwrap_or_default(),
+                offset: unsafe { request.offset() } as u64,
+            });
+        }
+        let handled = writes
+            .iter()
+            .all(|write| self.write_handlers.contains_key(&write.request.characteristic));
+        if handled {
+            let response = handle_writes(
+                &mut self.write_validators,
+                &mut self.write_handlers,
+                join_queued_writes(writes),
+            );
+            self.respond(&first, response.into());
+            return;
+        }
+        let id = self.track_request(first);
+        let event = if writes.len() == 1 {
+            let write = writes.remove(0);
+            PeripheralEvent::WriteRequest {
+                id,
+                request: write.request,
+                value: write.value,
+                offset: write.offset,
             }
+        } else {
+            PeripheralEvent::QueuedWriteRequest { id, writes }
         };
+        self.send_event(id, event).await;
+    }
+
+    async fn subscription_updated(
+        &mut self,
+        request: PeripheralRequest,
+        subscribed: bool,
+    ) {
+        let subscriber = (request.client.clone(), request.characteristic);
+        if subscribed {
+            self.subscribers.insert(subscriber, request.max_value_length);
+        } else {
+            self.subscribers.remove(&subscriber);
+        }
+        let value = self.characteristic_values.get(&request.characteristic).cloned();
+        let event = PeripheralEvent::CharacteristicSubscriptionUpdate {
+            request,
+            subscribed,
+            value,
+        };
+        if let Err(e) = self.peripheral_tx.send(event).await {
+            log::error!("Error sending peripheral event: {}", e);
+        }
     }
 
-    fn is_powered(self: &Self) -> bool {
+    fn track_request(&mut self, request: Retained<CBATTRequest>) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        self.pending_requests.insert(id, request);
+        id
+    }
+
+    // Hand the request to the app, answering it straight away if nobody is listening
+    async fn send_event(&mut self, id: u64, event: PeripheralEvent) {
+        if let Err(e) = self.peripheral_tx.send(event).await {
+            log::error!("Error sending peripheral event: {}", e);
+            if let Some(request) = self.pending_requests.remove(&id) {
+                self.reject_request(&request);
+            }
+        }
+    }
+
+    fn reject_request(&self, request: &CBATTRequest) {
+        unsafe {
+            self.cb_peripheral_manager
+                .respondToRequest_withResult(request, CBATTError::UnlikelyError)
+        };
+    }
+
+    fn respond_to_request(
+        &mut self,
+        id: u64,
+        response: PeripheralRequestResponse,
+    ) -> Result<(), Error> {
+        let Some(request) = self.pending_requests.remove(&id) else {
+            return Err(Error::from_string(
+                format!("No pending request with id {}", id),
+                ErrorType::NotFound,
+            ));
+        };
+        self.respond(&request, response);
+        Ok(())
+    }
+
+    fn respond(&self, request: &CBATTRequest, response: PeripheralRequestResponse) {
+        let result = match response {
+            PeripheralRequestResponse::Read(response) => {
+                unsafe { request.setValue(Some(&NSData::from_vec(response.value))) };
+                response.response.to_cb_error()
+            }
+            PeripheralRequestResponse::Write(response) => response.response.to_cb_error(),
+        };
+        unsafe {
+            self.cb_peripheral_manager
+                .respondToRequest_withResult(request, result)
+        };
+    }
+
+    fn is_powered(&self) -> bool {
         unsafe {
             let state = self.cb_peripheral_manager.state();
             state == CBManagerState::PoweredOn
         }
     }
 
-    async fn start_advertising(self: &Self, name: &str, uuids: &[Uuid]) -> Result<(), Error> {
-        if self
-            .peripheral_delegate
-            .is_waiting_for_advertisement_result()
-        {
-            return Err(Error::from_string("Already in progress".to_string()));
+    // Answered once the delegate reports the outcome, see AdvertisingStart
+    fn start_advertising(
+        &mut self,
+        name: Option<String>,
+        uuids: Vec<Uuid>,
+        responder: oneshot::Sender<Result<(), Error>>,
+    ) {
+        match self.advertise(name, uuids) {
+            Ok(()) => self.advertising_start = Some(AdvertisingStart::new(responder)),
+            Err(error) => {
+                let _ = responder.send(Err(error));
+            }
+        }
+    }
+
+    // Without a name only the services are advertised, none at all is valid too
+    fn advertise(&mut self, name: Option<String>, uuids: Vec<Uuid>) -> Result<(), Error> {
+        if self.advertising_start.is_some() {
+            return Err(Error::from_string(
+                "Already in progress".to_string(),
+                ErrorType::InProgress,
+            ));
+        }
+        for uuid in uuids.iter() {
+            validate_uuid(*uuid, "Advertised service")?;
         }
 
         let mut keys: Vec<&NSString> = vec![];
         let mut objects: Vec<Retained<AnyObject>> = vec![];
 
         unsafe {
-            keys.push(CBAdvertisementDataLocalNameKey);
-            objects.push(Retained::cast_unchecked(NSString::from_str(name)));
+            if let Some(name) = &name {
+                keys.push(CBAdvertisementDataLocalNameKey);
+                objects.push(Retained::cast_unchecked(NSString::from_str(name)));
+            }
 
-            keys.push(CBAdvertisementDataServiceUUIDsKey);
-            objects.push(Retained::cast_unchecked(NSArray::from_retained_slice(
-                uuids.iter().map(|u| uuid_to_cbuuid(u.clone())).collect(),
-            )));
+            if !uuids.is_empty() {
+                keys.push(CBAdvertisementDataServiceUUIDsKey);
+                let cb_uuids = uuids.iter().map(|u| uuid_to_cbuuid(*u)).collect::<Vec<_>>();
+                objects.push(Retained::cast_unchecked(NSArray::from_retained_slice(&cb_uuids)));
+            }
         }
 
         let advertising_data: Retained<NSDictionary<NSString, AnyObject>> =
             NSDictionary::from_retained_objects(&keys, &objects);
+        self.local_name = name;
+        self.advertised_uuids = uuids;
 
         unsafe {
             self.cb_peripheral_manager
                 .startAdvertising(Some(&advertising_data));
         }
+        Ok(())
+    }
 
-        return self
-            .peripheral_delegate
-            .ensure_advertisement_started()
-            .await;
+    async fn stop_advertising(&mut self) {
+        unsafe {
+            self.cb_peripheral_manager.stopAdvertising();
+        }
+        // Fail a start that is still waiting on the delegate rather than leave it dangling
+        if let Some(start) = self.advertising_start.take() {
+            log::debug!("Cancelled pending advertising start");
+            start.cancel();
+        }
+        // CoreBluetooth has no delegate callback for stopping
+        self.advertising_state_changed(false, None).await;
     }
 
-    fn stop_advertising(self: &Self) {
+    // Becomes the name of a start_advertising without one, or re-advertises with it straight away
+    // when advertising already. The restart is reported like any stop and start. A start still
+    // waiting on the delegate is rejected before stopping anything, but CoreBluetooth can still
+    // refuse the restarted advertisement, which then leaves advertising stopped.
+    async fn set_local_name(&mut self, name: String, responder: oneshot::Sender<Result<(), Error>>) {
+        if self.advertising_start.is_some() {
+            let _ = responder.send(Err(Error::from_string(
+                "Already in progress".to_string(),
+                ErrorType::InProgress,
+            )));
+            return;
+        }
+        if !self.is_advertising() {
+            self.local_name = Some(name);
+            let _ = responder.send(Ok(()));
+            return;
+        }
         unsafe {
             self.cb_peripheral_manager.stopAdvertising();
         }
+        self.advertising_state_changed(false, None).await;
+        let uuids = self.advertised_uuids.clone();
+        self.start_advertising(Some(name), uuids, responder);
+    }
+
+    fn advertise_beacon(
+        &mut self,
+        data: AdvertisementData,
+        responder: oneshot::Sender<Result<(), Error>>,
+    ) {
+        match data.validate() {
+            Ok(()) => self.start_advertising(data.local_name, data.service_uuids, responder),
+            Err(error) => {
+                let _ = responder.send(Err(error));
+            }
+        }
     }
 
-    fn is_advertising(self: &Self) -> bool {
+    async fn state_updated(&self, state: CentralState) {
+        let event = PeripheralEvent::StateUpdate {
+            is_powered: state == CentralState::PoweredOn,
+        };
+        self.state_tx.send_replace(state);
+        if let Err(e) = self.peripheral_tx.send(event).await {
+            log::error!("Error sending peripheral event: {}", e);
+        }
+    }
+
+    async fn advertising_state_changed(&self, advertising: bool, error: Option<String>) {
+        let event = PeripheralEvent::AdvertisingStateChanged { advertising, error };
+        if let Err(e) = self.peripheral_tx.send(event).await {
+            log::error!("Error sending peripheral event: {}", e);
+        }
+    }
+
+    fn is_advertising(&self) -> bool {
         unsafe { self.cb_peripheral_manager.isAdvertising() }
     }
 
-    async fn update_characteristic(
+    // Store the value and notify its subscribers. Without subscribers the value is only kept for
+    // reads and later subscribers, so no length limit applies.
+    fn update_characteristic(
         &mut self,
         characteristic: Uuid,
         value: Vec<u8>,
-    ) -> Result<(), Error> {
-        if let Some(char) = self.cached_characteristics.get(&characteristic) {
-            unsafe {
+        responder: oneshot::Sender<Result<(), Error>>,
+    ) {
+        if let Err(error) = check_notify_length(&self.subscribers, characteristic, value.len()) {
+            let _ = responder.send(Err(error));
+            return;
+        }
+        self.characteristic_values
+            .insert(characteristic, value.clone());
+        let subscribed = self
+            .subscribers
+            .keys()
+            .any(|(_, subscribed)| *subscribed == characteristic);
+        if !subscribed || !self.cached_characteristics.contains_key(&characteristic) {
+            let _ = responder.send(Ok(()));
+            return;
+        }
+        self.pending_updates.push_back(PendingUpdate {
+            characteristic,
+            value,
+            responder,
+        });
+        self.send_pending_updates();
+    }
+
+    // Notify in order until CoreBluetooth's transmit queue is full, the rest waits for
+    // peripheralManagerIsReadyToUpdateSubscribers
+    fn send_pending_updates(&mut self) {
+        while let Some(update) = self.pending_updates.pop_front() {
+            let Some(characteristic) = self.cached_characteristics.get(&update.characteristic) else {
+                let _ = update.responder.send(Ok(()));
+                continue;
+            };
+            let sent = unsafe {
                 self.cb_peripheral_manager
                     .updateValue_forCharacteristic_onSubscribedCentrals(
-                        &NSData::from_vec(value.clone()),
-                        char,
+                        &NSData::from_vec(update.value.clone()),
+                        characteristic,
                         None,
-                    );
+                    )
+            };
+            if !sent {
+                self.pending_updates.push_front(update);
+                return;
             }
+            let _ = update.responder.send(Ok(()));
         }
-        return self.peripheral_delegate.ensure_characteristic_updated(&characteristic).await;
+    }
+
+    // NOTE: Every subscriber receives the same notification, so the smallest central limit
+    // applies. Without subscribers assume the default MTU.
+    fn max_notify_length(&self) -> usize {
+        self.subscribers
+            .values()
+            .copied()
+            .min()
+            .unwrap_or(DEFAULT_ATT_MTU - ATT_HEADER_SIZE)
     }
 
     // Peripheral with cache value must only have Read permission, else it will crash
     // TODO: throw proper error, or catch Objc errors
-    async fn add_service(&mut self, service: &Service) -> Result<(), Error> {
+    // NOTE: The services resolver is keyed by service UUID so different services can be added
+    // concurrently, only adding the same service twice while the first is in flight is rejected.
+    async fn add_service(
+        &mut self,
+        service: &Service,
+        handlers: RequestHandlers,
+    ) -> Result<(), Error> {
         if self
             .peripheral_delegate
             .is_waiting_for_service_result(service.uuid)
         {
-            return Err(Error::from_string("Already in progress".to_string()));
+            return Err(Error::from_string(
+                "Already in progress".to_string(),
+                ErrorType::InProgress,
+            ));
+        }
+        validate_uuid(service.uuid, "Service")?;
+        for characteristic in service.characteristics.iter() {
+            validate_uuid(characteristic.uuid, "Characteristic")?;
+            for descriptor in characteristic.descriptors.iter() {
+                validate_uuid(descriptor.uuid, "Descriptor")?;
+            }
         }
 
         unsafe {
@@ -214,6 +640,9 @@ impl PeripheralManager {
                 let cb_char = parse_characteristic(char);
                 characteristics.push(Retained::into_super(cb_char.clone()));
                 self.cached_characteristics.insert(char.uuid, cb_char);
+                if let Some(value) = &char.value {
+                    self.characteristic_values.insert(char.uuid, value.clone());
+                }
             }
 
             let mutable_service: Retained<CBMutableService> =
@@ -230,16 +659,239 @@ impl PeripheralManager {
 
             self.cb_peripheral_manager.addService(&mutable_service);
 
-            return self
-                .peripheral_delegate
+            self.peripheral_delegate
                 .ensure_service_added(service.uuid)
-                .await;
+                .await?;
         }
+        self.read_handlers.extend(handlers.read);
+        self.write_handlers.extend(handlers.write);
+        self.write_validators.extend(handlers.validate_write);
+        Ok(())
     }
 }
 
-pub fn is_authorized() -> bool {
-    let authorization = unsafe { CBManager::authorization_class() };
-    return authorization != CBManagerAuthorization::Restricted
-        && authorization != CBManagerAuthorization::Denied;
+// The smallest limit of the centrals subscribed to `characteristic` applies, None subscribed
+// means the value is only stored
+fn check_notify_length(
+    subscribers: &HashMap<(String, Uuid), usize>,
+    characteristic: Uuid,
+    len: usize,
+) -> Result<(), Error> {
+    let limit = subscribers
+        .iter()
+        .filter(|((_, subscribed), _)| *subscribed == characteristic)
+        .map(|(_, max_length)| *max_length)
+        .min();
+    match limit {
+        Some(limit) if len > limit => Err(Error::from_string(
+            format!(
+                "Notification of {} bytes exceeds the {} bytes subscribers accept",
+                len, limit
+            ),
+            ErrorType::ValueTooLong,
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Resolves at `deadline`, never without one
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => future::pending::<()>().await,
+    }
+}
+
+// Check every part before applying any, the first refused part answers the whole write. Once all
+// are accepted every part is applied in order.
+fn handle_writes(
+    validators: &mut HashMap<Uuid, WriteValidator>,
+    handlers: &mut HashMap<Uuid, WriteHandler>,
+    writes: Vec<QueuedWrite>,
+) -> WriteRequestResponse {
+    for write in writes.iter() {
+        let Some(validator) = validators.get_mut(&write.request.characteristic) else {
+            continue;
+        };
+        let response = validator(&write.value, write.offset, &write.request);
+        if response != RequestResponse::Success {
+            return WriteRequestResponse { response };
+        }
+    }
+    let mut result = WriteRequestResponse {
+        response: RequestResponse::Success,
+    };
+    for write in writes {
+        let Some(handler) = handlers.get_mut(&write.request.characteristic) else {
+            continue;
+        };
+        let response = handler(&write.value, write.offset, &write.request);
+        if response.response != RequestResponse::Success {
+            log::warn!(
+                "Write handler of {} refused a validated write: {:?}",
+                write.request.characteristic,
+                response.response
+            );
+            if result.response == RequestResponse::Success {
+                result = response;
+            }
+        }
+    }
+    result
+}
+
+// Join consecutive parts of the same characteristic so a write handler sees a long write as one
+// value at its starting offset
+fn join_queued_writes(writes: Vec<QueuedWrite>) -> Vec<QueuedWrite> {
+    let mut joined: Vec<QueuedWrite> = Vec::with_capacity(writes.len());
+    for write in writes {
+        match joined.last_mut() {
+            Some(last)
+                if last.request.characteristic == write.request.characteristic
+                    && last.offset + last.value.len() as u64 == write.offset =>
+            {
+                last.value.extend(write.value);
+            }
+            _ => joined.push(write),
+        }
+    }
+    joined
+}
+
+// The API structs default to the nil UUID, publishing it is almost always a forgotten field
+fn validate_uuid(uuid: Uuid, attribute: &str) -> Result<(), Error> {
+    if uuid.is_nil() {
+        return Err(Error::from_string(
+            format!("{} UUID is not set", attribute),
+            ErrorType::InvalidUuid,
+        ));
+    }
+    Ok(())
+}
+
+// Requests for characteristics no longer attached to a service can't be described to the app
+fn peripheral_request(request: &CBATTRequest) -> Option<PeripheralRequest> {
+    unsafe {
+        let characteristic = request.characteristic();
+        let service = characteristic.service()?;
+        let central = request.central();
+        Some(PeripheralRequest {
+            client: central.identifier().to_string(),
+            service: service.get_uuid(),
+            characteristic: characteristic.get_uuid(),
+            max_value_length: central.maximumUpdateValueLength(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // stop_advertising cancels the pending start, its caller must hear about it
+    #[test]
+    fn stopping_a_pending_start_cancels_it() {
+        let (responder, mut receiver) = oneshot::channel();
+        AdvertisingStart::new(responder).cancel();
+        let error = receiver.try_recv().unwrap().unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::Cancelled);
+    }
+
+    #[test]
+    fn delegate_error_fails_the_start() {
+        let (responder, mut receiver) = oneshot::channel();
+        AdvertisingStart::new(responder).resolve(Some("Advertising failed".to_string()));
+        let error = receiver.try_recv().unwrap().unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::CoreBluetooth);
+    }
+
+    #[test]
+    fn confirmed_start_succeeds() {
+        let (responder, mut receiver) = oneshot::channel();
+        AdvertisingStart::new(responder).resolve(None);
+        assert!(receiver.try_recv().unwrap().is_ok());
+    }
+
+    #[test]
+    fn oversized_notification_is_rejected() {
+        let characteristic = Uuid::from_u128(1);
+        let subscribers = HashMap::from([
+            (("central-a".to_string(), characteristic), 182),
+            (("central-b".to_string(), characteristic), 20),
+        ]);
+        let error = check_notify_length(&subscribers, characteristic, 21).unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::ValueTooLong);
+        assert!(check_notify_length(&subscribers, characteristic, 20).is_ok());
+    }
+
+    #[test]
+    fn value_without_subscribers_is_not_limited() {
+        let characteristic = Uuid::from_u128(1);
+        let other = (("central-a".to_string(), Uuid::from_u128(2)), 20);
+        let subscribers = HashMap::from([other]);
+        assert!(check_notify_length(&subscribers, characteristic, 512).is_ok());
+        assert!(check_notify_length(&HashMap::new(), characteristic, 512).is_ok());
+    }
+
+    fn queued_write(characteristic: Uuid, value: &[u8]) -> QueuedWrite {
+        QueuedWrite {
+            request: PeripheralRequest {
+                client: "central".to_string(),
+                service: Uuid::from_u128(1),
+                characteristic,
+                max_value_length: 20,
+            },
+            value: value.to_vec(),
+            offset: 0,
+        }
+    }
+
+    // A refused part must leave the parts before it unapplied
+    #[test]
+    fn refused_part_applies_nothing() {
+        let (first, second) = (Uuid::from_u128(2), Uuid::from_u128(3));
+        let applied = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut handlers: HashMap<Uuid, WriteHandler> = HashMap::new();
+        for characteristic in [first, second] {
+            let applied = applied.clone();
+            handlers.insert(
+                characteristic,
+                Box::new(move |value: &[u8], _, _: &PeripheralRequest| {
+                    applied.lock().unwrap().push(value.to_vec());
+                    WriteRequestResponse {
+                        response: RequestResponse::Success,
+                    }
+                }),
+            );
+        }
+        let mut validators: HashMap<Uuid, WriteValidator> = HashMap::new();
+        validators.insert(
+            second,
+            Box::new(|value: &[u8], _, _: &PeripheralRequest| {
+                if value.len() > 2 {
+                    RequestResponse::InvalidAttributeValueLength
+                } else {
+                    RequestResponse::Success
+                }
+            }),
+        );
+
+        let writes = vec![queued_write(first, &[1]), queued_write(second, &[1, 2, 3])];
+        let response = handle_writes(&mut validators, &mut handlers, writes);
+        assert_eq!(response.response, RequestResponse::InvalidAttributeValueLength);
+        assert!(applied.lock().unwrap().is_empty());
+
+        let writes = vec![queued_write(first, &[1]), queued_write(second, &[1, 2])];
+        let response = handle_writes(&mut validators, &mut handlers, writes);
+        assert_eq!(response.response, RequestResponse::Success);
+        assert_eq!(*applied.lock().unwrap(), vec![vec![1], vec![1, 2]]);
+    }
+
+    #[test]
+    fn nil_uuid_is_rejected() {
+        let error = validate_uuid(Uuid::nil(), "Characteristic").unwrap_err();
+        assert_eq!(error.error_type(), ErrorType::InvalidUuid);
+        assert!(error.to_string().contains("Characteristic UUID is not set"));
+        assert!(validate_uuid(Uuid::from_u128(1), "Characteristic").is_ok());
+    }
 }
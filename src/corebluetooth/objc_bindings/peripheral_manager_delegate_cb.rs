@@ -1,19 +1,19 @@
 use super::mac_extensions_cb::UuidExtension;
 use crate::{
     Error, ErrorType,
-    api::peripheral_event::{
-        PeripheralEvent, PeripheralRequest, ReadRequestResponse, RequestResponse,
-        WriteRequestResponse,
+    api::central_event::CentralState,
+    api::peripheral_event::{PeripheralRequest, RequestResponse},
+    corebluetooth::objc_bindings::{
+        ServiceResolver, central_manager_delegate_cb::convert_state,
     },
-    corebluetooth::objc_bindings::{AdvertisementResolver, ServiceResolver},
 };
 use ::futures::executor;
-use objc2::{AnyThread, DeclaredClass, define_class, msg_send, rc::Retained};
+use objc2::{AnyThread, DeclaredClass, Message, define_class, msg_send, rc::Retained};
 use objc2_core_bluetooth::{
-    CBATTError, CBATTRequest, CBCentral, CBCharacteristic, CBManagerState, CBPeripheralManager,
+    CBATTError, CBATTRequest, CBCentral, CBCharacteristic, CBPeripheralManager,
     CBPeripheralManagerDelegate, CBService,
 };
-use objc2_foundation::{NSArray, NSData, NSError, NSObject, NSObjectProtocol};
+use objc2_foundation::{NSArray, NSError, NSObject, NSObjectProtocol};
 use std::{
     sync::{Arc, Mutex},
 };
@@ -27,7 +27,6 @@ use uuid::Uuid;
 pub struct IVars {
     pub sender: Sender<PeripheralManagerDelegateEvent>,
     pub services_resolver: Arc<Mutex<ServiceResolver>>,
-    pub advertisement_resolver: Arc<Mutex<AdvertisementResolver>>,
 }
 
 // Macro for defining the ObjC class
@@ -45,9 +44,14 @@ define_class! {
         #[unsafe(method(peripheralManagerDidUpdateState:))]
          fn delegate_peripheralmanagerdidupdatestate(&self, peripheral: &CBPeripheralManager){
                 let state = unsafe { peripheral.state() };
-                self.send_event(PeripheralEvent::StateUpdate { is_powered : state == CBManagerState::PoweredOn });
+                self.send_event(PeripheralManagerDelegateEvent::StateUpdate { state: convert_state(state) });
          }
 
+        #[unsafe(method(peripheralManagerIsReadyToUpdateSubscribers:))]
+        fn delegate_peripheralmanagerisreadytoupdatesubscribers(&self, _: &CBPeripheralManager){
+            self.send_event(PeripheralManagerDelegateEvent::ReadyToUpdateSubscribers);
+        }
+
         #[unsafe(method(peripheralManagerDidStartAdvertising:error:))]
         fn delegate_peripheralmanagerdidstartadvertising_error(&self, _: &CBPeripheralManager,error: Option<&NSError>){
             let mut error_desc: Option<String> = None;
@@ -55,13 +59,10 @@ define_class! {
                 error_desc = Some(error.localizedDescription().to_string());
             }
             log::debug!("Advertising, Error: {error_desc:?}");
-            if let Ok(mut resolver) = self.ivars().advertisement_resolver.lock() {
-                let sender_opt = resolver.take();
-                drop(resolver);
-                if let Some(sender) = sender_opt {
-                    let _ = sender.send(error_desc);
-                }
-            }
+            // The manager thread resolves the pending start_advertising
+            self.send_event(PeripheralManagerDelegateEvent::AdvertisingStarted {
+                error: error_desc,
+            });
         }
 
         #[unsafe(method(peripheralManager:didAddService:error:))]
@@ -73,11 +74,11 @@ define_class! {
             log::debug!("AddServices, Error: {error_desc:?}");
 
 
-            if let Ok(mut resolver) = self.ivars().services_resolver.lock() {
-                if let Some(sender) = resolver.take(&service.get_uuid()) {
-                    drop(resolver); // Explicit drop before send
-                    let _ = sender.send(error_desc);
-                }
+            if let Ok(mut resolver) = self.ivars().services_resolver.lock()
+                && let Some(sender) = resolver.take(&service.get_uuid())
+            {
+                drop(resolver); // Explicit drop before send
+                let _ = sender.send(error_desc);
             }
         }
 
@@ -93,11 +94,12 @@ define_class! {
                 if service.is_none() {
                     return;
                 }
-                self.send_event(PeripheralEvent::CharacteristicSubscriptionUpdate {
+                self.send_event(PeripheralManagerDelegateEvent::SubscriptionUpdate {
                     request: PeripheralRequest {
                         client: central.identifier().to_string(),
                         service: characteristic.service().unwrap().get_uuid(),
                         characteristic: characteristic.get_uuid(),
+                        max_value_length: central.maximumUpdateValueLength(),
                     },
                     subscribed: true,
                 });
@@ -116,11 +118,12 @@ define_class! {
                 return;
             }
 
-            self.send_event(PeripheralEvent::CharacteristicSubscriptionUpdate {
+            self.send_event(PeripheralManagerDelegateEvent::SubscriptionUpdate {
                request: PeripheralRequest {
                     client: central.identifier().to_string(),
                     service: characteristic.service().unwrap().get_uuid(),
                     characteristic: characteristic.get_uuid(),
+                    max_value_length: central.maximumUpdateValueLength(),
                 },
                 subscribed: false,
             });
@@ -129,60 +132,24 @@ define_class! {
         #[unsafe(method(peripheralManager:didReceiveReadRequest:))]
          fn delegate_peripheralmanager_didreceivereadrequest(
             &self,
-            manager: &CBPeripheralManager,
+            _: &CBPeripheralManager,
             request: &CBATTRequest,
         ){
-            unsafe{
-                let service = request.characteristic().service();
-                if service.is_none() {
-                    return;
-                }
-                let central = request.central();
-                let characteristic = request.characteristic();
-
-                self.send_read_request(
-                    PeripheralRequest{
-                        client: central.identifier().to_string(),
-                        service: characteristic.service().unwrap().get_uuid(),
-                        characteristic: characteristic.get_uuid(),
-                    },
-                    manager,
-                    request,
-                );
-            }
+            self.send_event(PeripheralManagerDelegateEvent::ReadRequest {
+                request: request.retain(),
+            });
         }
 
         #[unsafe(method(peripheralManager:didReceiveWriteRequests:))]
          fn delegate_peripheralmanager_didreceivewriterequests(
             &self,
-            manager: &CBPeripheralManager,
+            _: &CBPeripheralManager,
             requests: &NSArray<CBATTRequest>,
         ){
-            for request in requests {
-                unsafe{
-                    let service = request.characteristic().service();
-                    if service.is_none() {
-                        return;
-                    }
-                    let mut value: Vec<u8> = Vec::new();
-                    if let Some(ns_data) = request.value() {
-                       value = ns_data.as_bytes_unchecked().to_vec();
-                    }
-                    let central = request.central();
-                    let characteristic = request.characteristic();
-
-                    self.send_write_request(
-                        PeripheralRequest{
-                             client: central.identifier().to_string(),
-                            service: characteristic.service().unwrap().get_uuid(),
-                            characteristic: characteristic.get_uuid(),
-                        },
-                        manager,
-                        &request,
-                        value,
-                    );
-                }
-            }
+            // The requests of one callback are answered together, keep them together
+            self.send_event(PeripheralManagerDelegateEvent::WriteRequests {
+                requests: requests.to_vec(),
+            });
         }
     }
 }
@@ -192,42 +159,15 @@ impl PeripheralManagerDelegate {
         let this = PeripheralManagerDelegate::alloc().set_ivars(IVars {
             sender,
             services_resolver: Arc::new(Mutex::new(ServiceResolver::new())),
-            advertisement_resolver: Arc::new(Mutex::new(AdvertisementResolver::new()))
         });
         unsafe { msg_send![super(this), init] }
     }
 
-    pub fn is_waiting_for_advertisement_result(&self) -> bool {
-        if let Ok(resolver) = self.ivars().advertisement_resolver.lock() {
-            return resolver.is_waiting();
-        }
-        return false;
-    }
-
-    /// Wait for delegate to ensure advertisement started successfully
-    pub async fn ensure_advertisement_started(&self) -> Result<(), Error> {
-        let (sender, receiver) = oneshot::channel::<Option<String>>();
-        {
-            if let Ok(mut resolver) = self.ivars().advertisement_resolver.lock() {
-                resolver.register(sender);
-            }
-        }
-
-        let event = timeout(Duration::from_secs(5), receiver).await;
-
-        {
-            if let Ok(mut resolver) = self.ivars().advertisement_resolver.lock() {
-                resolver.cancel();
-            }
-        }
-        return self.resolve_event(event);
-    }
-
     pub fn is_waiting_for_service_result(&self, service: Uuid) -> bool {
         if let Ok(resolver) = self.ivars().services_resolver.lock() {
             return resolver.is_waiting_for(&service);
         }
-        return false;
+        false
     }
 
     // Wait for event from delegate if service added successfully
@@ -239,15 +179,21 @@ impl PeripheralManagerDelegate {
             }
         }
 
-        let event = timeout(Duration::from_secs(5), receiver).await;
+        let mut receiver = receiver;
+        let mut event = timeout(Duration::from_secs(5), &mut receiver).await;
 
-        {
-            if let Ok(mut resolver) = self.ivars().services_resolver.lock() {
-                resolver.cancel(&service);
-            }
+        // Taking the sender back under the lock decides the race with didAddService: if it is
+        // gone the delegate already owns it and the confirmation is on its way, so wait for it
+        // instead of reporting a timeout for a service that was added.
+        let delegate_took_sender = match self.ivars().services_resolver.lock() {
+            Ok(mut resolver) => resolver.take(&service).is_none(),
+            Err(_) => false,
+        };
+        if event.is_err() && delegate_took_sender {
+            event = Ok(receiver.await);
         }
 
-        return self.resolve_event(event);
+        self.resolve_event(event)
     }
 
     fn resolve_event(
@@ -277,13 +223,15 @@ impl PeripheralManagerDelegate {
             return Err(Error::from_string(error, ErrorType::CoreBluetooth));
         }
 
-        return Ok(());
+        Ok(())
     }
 }
 
 /// Event handler
 impl PeripheralManagerDelegate {
-    fn send_event(&self, event: PeripheralEvent) {
+    // NOTE: Only hands the event over to the manager thread, requests are answered later from
+    // there so the dispatch queue is never held while the app works out the answer.
+    fn send_event(&self, event: PeripheralManagerDelegateEvent) {
         let sender = self.ivars().sender.clone();
         executor::block_on(async {
             if let Err(e) = sender.send(event).await {
@@ -291,89 +239,82 @@ impl PeripheralManagerDelegate {
             }
         });
     }
-
-    fn send_read_request(
-        &self,
-        peripheral_request: PeripheralRequest,
-        manager: &CBPeripheralManager,
-        request: &CBATTRequest,
-    ) {
-        let sender = self.ivars().sender.clone();
-        unsafe {
-            executor::block_on(async {
-                let (resp_tx, resp_rx) = oneshot::channel::<ReadRequestResponse>();
-
-                if let Err(e) = sender
-                    .send(PeripheralEvent::ReadRequest {
-                        request: peripheral_request,
-                        offset: request.offset() as u64,
-                        responder: resp_tx,
-                    })
-                    .await
-                {
-                    log::error!("Error sending delegate event: {}", e);
-                    return;
-                }
-
-                let mut cb_att_error = CBATTError::InvalidHandle;
-                if let Ok(result) = resp_rx.await {
-                    cb_att_error = result.response.to_cb_error();
-                    request.setValue(Some(&NSData::from_vec(result.value)));
-                }
-                manager.respondToRequest_withResult(request, cb_att_error);
-            });
-        };
-    }
-
-    fn send_write_request(
-        &self,
-        peripheral_request: PeripheralRequest,
-        manager: &CBPeripheralManager,
-        request: &CBATTRequest,
-        value: Vec<u8>,
-    ) {
-        let sender = self.ivars().sender.clone();
-        unsafe {
-            executor::block_on(async {
-                let (resp_tx, resp_rx) = oneshot::channel::<WriteRequestResponse>();
-
-                if let Err(e) = sender
-                    .send(PeripheralEvent::WriteRequest {
-                        request: peripheral_request,
-                        value,
-                        offset: request.offset() as u64,
-                        responder: resp_tx,
-                    })
-                    .await
-                {
-                    log::error!("Error sending delegate event: {}", e);
-                    return;
-                }
-
-                let mut cb_att_error = CBATTError::InvalidHandle;
-                if let Ok(result) = resp_rx.await {
-                    cb_att_error = result.response.to_cb_error();
-                }
-
-                manager.respondToRequest_withResult(request, cb_att_error);
-            });
-        };
-    }
 }
 
+// Internal events from the delegate to the manager thread, which turns them into the public
+// `PeripheralEvent`s
 pub enum PeripheralManagerDelegateEvent {
-
-} 
+    StateUpdate { state: CentralState },
+    // The transmit queue has room again after updateValue returned false
+    ReadyToUpdateSubscribers,
+    ReadRequest { request: Retained<CBATTRequest> },
+    WriteRequests { requests: Vec<Retained<CBATTRequest>> },
+    AdvertisingStarted { error: Option<String> },
+    SubscriptionUpdate {
+        request: PeripheralRequest,
+        subscribed: bool,
+    },
+}
 
 impl RequestResponse {
-    fn to_cb_error(self) -> CBATTError {
+    pub fn to_cb_error(self) -> CBATTError {
         match self {
             RequestResponse::Success => CBATTError::Success,
             RequestResponse::InvalidHandle => CBATTError::InvalidHandle,
+            RequestResponse::ReadNotPermitted => CBATTError::ReadNotPermitted,
+            RequestResponse::WriteNotPermitted => CBATTError::WriteNotPermitted,
+            RequestResponse::InvalidPdu => CBATTError::InvalidPdu,
+            RequestResponse::InsufficientAuthentication => CBATTError::InsufficientAuthentication,
             RequestResponse::RequestNotSupported => CBATTError::RequestNotSupported,
             RequestResponse::InvalidOffset => CBATTError::InvalidOffset,
+            RequestResponse::InsufficientAuthorization => CBATTError::InsufficientAuthorization,
+            RequestResponse::PrepareQueueFull => CBATTError::PrepareQueueFull,
+            RequestResponse::AttributeNotFound => CBATTError::AttributeNotFound,
+            RequestResponse::AttributeNotLong => CBATTError::AttributeNotLong,
+            RequestResponse::InsufficientEncryptionKeySize => {
+                CBATTError::InsufficientEncryptionKeySize
+            }
+            RequestResponse::InvalidAttributeValueLength => {
+                CBATTError::InvalidAttributeValueLength
+            }
             RequestResponse::UnlikelyError => CBATTError::UnlikelyError,
+            RequestResponse::InsufficientEncryption => CBATTError::InsufficientEncryption,
+            RequestResponse::UnsupportedGroupType => CBATTError::UnsupportedGroupType,
+            RequestResponse::InsufficientResources => CBATTError::InsufficientResources,
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every response against its ATT error code (Core Specification Vol 3, Part F, 3.4.1.1)
+    #[test]
+    fn every_response_maps_to_its_att_error_code() {
+        let table = [
+            (RequestResponse::Success, 0x00),
+            (RequestResponse::InvalidHandle, 0x01),
+            (RequestResponse::ReadNotPermitted, 0x02),
+            (RequestResponse::WriteNotPermitted, 0x03),
+            (RequestResponse::InvalidPdu, 0x04),
+            (RequestResponse::InsufficientAuthentication, 0x05),
+            (RequestResponse::RequestNotSupported, 0x06),
+            (RequestResponse::InvalidOffset, 0x07),
+            (RequestResponse::InsufficientAuthorization, 0x08),
+            (RequestResponse::PrepareQueueFull, 0x09),
+            (RequestResponse::AttributeNotFound, 0x0A),
+            (RequestResponse::AttributeNotLong, 0x0B),
+            (RequestResponse::InsufficientEncryptionKeySize, 0x0C),
+            (RequestResponse::InvalidAttributeValueLength, 0x0D),
+            (RequestResponse::UnlikelyError, 0x0E),
+            (RequestResponse::InsufficientEncryption, 0x0F),
+            (RequestResponse::UnsupportedGroupType, 0x10),
+            (RequestResponse::InsufficientResources, 0x11),
+        ];
+        for (response, code) in table {
+            assert_eq!(response.to_cb_error(), CBATTError(code));
+        }
+    }
+}
@@ -1,14 +1,29 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
-use tokio::sync::{mpsc::Sender, oneshot};
+use tokio::sync::{
+    mpsc::{self, Sender},
+    oneshot, watch,
+};
 use uuid::Uuid;
 
 use crate::{
     Result,
-    api::{peripheral::PeripheralManager, peripheral_event::PeripheralEvent, service::Service},
+    api::{
+        advertisement::AdvertisementData,
+        central_event::CentralState,
+        config::Config,
+        peripheral::PeripheralManager,
+        peripheral_event::{PeripheralEvent, PeripheralRequestResponse, RequestHandlers},
+        service::Service,
+    },
+    corebluetooth::objc_bindings::peripheral_manager_cb::run_peripheral_thread,
 };
 
 pub struct Peripheral {
     manager_tx: Sender<PeripheralManagerCommand>,
+    // Latest state reported by CoreBluetooth
+    state_rx: watch::Receiver<CentralState>,
 }
 
 #[async_trait]
@@ -16,31 +31,137 @@ impl PeripheralManager for Peripheral {
     type PeripheralManager = Self;
 
     async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self> {
-        todo!()
+        Self::new_with_config(sender_tx, Config::default()).await
+    }
+
+    async fn new_with_config(sender_tx: Sender<PeripheralEvent>, config: Config) -> Result<Self> {
+        super::ensure_authorized()?;
+        let (manager_tx, manager_rx) = mpsc::channel::<PeripheralManagerCommand>(256);
+        let (state_tx, state_rx) = watch::channel(CentralState::Unknown);
+        run_peripheral_thread(sender_tx, manager_rx, state_tx, config);
+        Ok(Peripheral {
+            manager_tx,
+            state_rx,
+        })
     }
 
     async fn is_powered(&mut self) -> Result<bool> {
-        todo!()
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::IsPowered { responder })
+            .await?;
+        receiver.await?
+    }
+
+    async fn wait_until_powered_on(&mut self, timeout: Duration) -> Result<()> {
+        super::wait_until_powered_on(self.state_rx.clone(), timeout).await
     }
 
     async fn is_advertising(&mut self) -> Result<bool> {
-        todo!()
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::IsAdvertising { responder })
+            .await?;
+        receiver.await?
     }
 
-    async fn start_advertising(&mut self, name: &str, uuids: &[Uuid]) -> Result<()> {
-        todo!()
+    async fn start_advertising(&mut self, name: Option<&str>, uuids: &[Uuid]) -> Result<()> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::StartAdvertising {
+                name: name.map(str::to_string),
+                uuids: uuids.to_vec(),
+                responder,
+            })
+            .await?;
+        receiver.await?
     }
 
     async fn stop_advertising(&mut self) -> Result<()> {
-        todo!()
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::StopAdvertising { responder })
+            .await?;
+        receiver.await?
+    }
+
+    async fn advertise_beacon(&mut self, data: AdvertisementData) -> Result<()> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::AdvertiseBeacon { data, responder })
+            .await?;
+        receiver.await?
     }
 
-    async fn add_service(&mut self, service: &Service) -> Result<()> {
-        todo!()
+    async fn local_name(&mut self) -> Result<Option<String>> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::LocalName { responder })
+            .await?;
+        receiver.await?
+    }
+
+    async fn set_local_name(&mut self, name: &str) -> Result<()> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::SetLocalName {
+                name: name.to_string(),
+                responder,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    async fn add_service_with_handlers(
+        &mut self,
+        service: &Service,
+        handlers: RequestHandlers,
+    ) -> Result<()> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::AddService {
+                service: service.clone(),
+                handlers,
+                responder,
+            })
+            .await?;
+        receiver.await?
     }
 
     async fn update_characteristic(&mut self, characteristic: Uuid, value: Vec<u8>) -> Result<()> {
-        todo!()
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::UpdateCharacteristic {
+                characteristic,
+                value,
+                responder,
+            })
+            .await?;
+        receiver.await?
+    }
+
+    async fn max_notify_length(&mut self) -> Result<usize> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::MaxNotifyLength { responder })
+            .await?;
+        receiver.await?
+    }
+
+    async fn respond_to_request(
+        &mut self,
+        id: u64,
+        response: PeripheralRequestResponse,
+    ) -> Result<()> {
+        let (responder, receiver) = oneshot::channel();
+        self.manager_tx
+            .send(PeripheralManagerCommand::RespondToRequest {
+                id,
+                response,
+                responder,
+            })
+            .await?;
+        receiver.await?
     }
 }
 
@@ -48,6 +169,14 @@ impl Peripheral {
 
 }
 
+// Ends the background peripheral manager. A full queue can't take the command, the loop then
+// still ends once it works through the queue and finds the channel closed.
+impl Drop for Peripheral {
+    fn drop(&mut self) {
+        let _ = self.manager_tx.try_send(PeripheralManagerCommand::Shutdown);
+    }
+}
+
 pub enum PeripheralManagerCommand {
     IsPowered {
         responder: oneshot::Sender<Result<bool>>,
@@ -56,15 +185,31 @@ pub enum PeripheralManagerCommand {
         responder: oneshot::Sender<Result<bool>>,
     },
     StartAdvertising {
-        name: String,
+        name: Option<String>,
         uuids: Vec<Uuid>,
         responder: oneshot::Sender<Result<()>>,
     },
     StopAdvertising {
         responder: oneshot::Sender<Result<()>>,
     },
+    // Stop advertising and end the background peripheral manager, sent when the Peripheral is
+    // dropped
+    Shutdown,
+    AdvertiseBeacon {
+        data: AdvertisementData,
+        responder: oneshot::Sender<Result<()>>,
+    },
+    LocalName {
+        responder: oneshot::Sender<Result<Option<String>>>,
+    },
+    SetLocalName {
+        name: String,
+        responder: oneshot::Sender<Result<()>>,
+    },
     AddService {
         service: Service,
+        // Registered once the service was added
+        handlers: RequestHandlers,
         responder: oneshot::Sender<Result<()>>,
     },
     UpdateCharacteristic {
@@ -72,4 +217,33 @@ pub enum PeripheralManagerCommand {
         value: Vec<u8>,
         responder: oneshot::Sender<Result<()>>,
     },
+    // Largest notification payload every current subscriber accepts
+    MaxNotifyLength {
+        responder: oneshot::Sender<Result<usize>>,
+    },
+    RespondToRequest {
+        id: u64,
+        response: PeripheralRequestResponse,
+        responder: oneshot::Sender<Result<()>>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The second Peripheral must get a manager thread of its own rather than a dead channel
+    #[tokio::test]
+    async fn peripherals_constructed_one_after_the_other_both_answer() {
+        let (sender_tx, _sender_rx) = mpsc::channel(16);
+        let Ok(mut first) = Peripheral::new(sender_tx.clone()).await else {
+            // Bluetooth access refused on this machine
+            return;
+        };
+        assert!(first.local_name().await.is_ok());
+        drop(first);
+
+        let mut second = Peripheral::new(sender_tx).await.unwrap();
+        assert!(second.local_name().await.is_ok());
+    }
 }
@@ -1,15 +1,33 @@
 mod corebluetooth;
 pub mod api;
+pub mod util;
+
+// The CoreBluetooth backed managers, everything else of the backend stays internal
+pub use corebluetooth::adapter::Adapter;
+pub use corebluetooth::central_manager::Central;
+pub use corebluetooth::peripheral_manager::Peripheral;
 use std::error;
 use std::result;
 use std::fmt;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ErrorType {
     CoreBluetooth,
     PermissionDenied,
     ChannelError,
+    Timeout,
+    NotFound,
+    InvalidValue,
+    UnknownPeripheral,
+    ValueTooLong,
+    InProgress,
+    NotSupported,
+    Cancelled,
+    InvalidUuid,
+    AdvertisementTooLarge,
+    // Bluetooth access was denied by the user or restricted by a device policy
+    Unauthorized,
 }
 
 impl From<ErrorType> for &'static str {
@@ -18,12 +36,23 @@ impl From<ErrorType> for &'static str {
             ErrorType::CoreBluetooth => "CoreBluetooth",
             ErrorType::PermissionDenied => "PermissionDenied",
             ErrorType::ChannelError => "ChannelError",
+            ErrorType::Timeout => "Timeout",
+            ErrorType::NotFound => "NotFound",
+            ErrorType::InvalidValue => "InvalidValue",
+            ErrorType::UnknownPeripheral => "UnknownPeripheral",
+            ErrorType::ValueTooLong => "ValueTooLong",
+            ErrorType::InProgress => "InProgress",
+            ErrorType::NotSupported => "NotSupported",
+            ErrorType::Cancelled => "Cancelled",
+            ErrorType::InvalidUuid => "InvalidUuid",
+            ErrorType::AdvertisementTooLarge => "AdvertisementTooLarge",
+            ErrorType::Unauthorized => "Unauthorized",
         }
     }
 }
 
 impl fmt::Display for ErrorType {
-    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let error_type: &str = self.clone().into();
         write!(f, "<RustyCore {} Error>", error_type)
     }
@@ -35,7 +64,6 @@ impl error::Error for ErrorType {}
 pub struct Error {
     name: String,
     description: String,
-    combined_description: String,
     error_type: ErrorType,
 }
 
@@ -43,11 +71,9 @@ impl Error {
     pub fn new<T: Into<String>>(name: T, description: T, error_type: ErrorType) -> Self {
         let name: String = name.into();
         let description: String = description.into();
-        let combined_description = format!("{}: {}", name, description);
         Error {
             name,
             description,
-            combined_description,
             error_type,
         }
     }
@@ -55,30 +81,30 @@ impl Error {
     pub fn from_type(error_type: ErrorType) -> Self {
         let name: String = error_type.to_string();
         let description: String = error_type.to_string();
-        let combined_description = format!("{}: {}", name, description);
         Error {
             name,
             description,
-            combined_description,
             error_type,
         }
     }
 
+    pub fn error_type(&self) -> ErrorType {
+        self.error_type.clone()
+    }
+
     pub fn from_string(error: String, error_type: ErrorType) -> Self {
         let name: String = error_type.to_string();
         let description: String = error;
-        let combined_description = format!("{}: {}", name, description);
         Error {
             name,
             description,
-            combined_description,
             error_type,
         }
     }
 }
 
 impl fmt::Display for Error {
-    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let error_type: &str = self.error_type.clone().into();
         write!(
             f,
@@ -89,13 +115,32 @@ impl fmt::Display for Error {
 }
 
 impl error::Error for Error {
-    fn description(self: &Self) -> &str {
-        &self.combined_description
-    }
-
-    fn source(self: &Self) -> Option<&(dyn error::Error + 'static)> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         Some(&self.error_type)
     }
 }
 
 pub type Result<T> = result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_from_string_keeps_message_and_type() {
+        let error = Error::from_string("Characteristic not found".to_string(), ErrorType::NotFound);
+        assert_eq!(error.error_type(), ErrorType::NotFound);
+        let message = error.to_string();
+        assert!(message.contains("NotFound"));
+        assert!(message.contains("Characteristic not found"));
+    }
+
+    #[test]
+    fn error_new_keeps_name_and_description() {
+        let error = Error::new("Write", "Value is too long", ErrorType::ValueTooLong);
+        assert_eq!(error.error_type(), ErrorType::ValueTooLong);
+        let message = error.to_string();
+        assert!(message.contains("Write"));
+        assert!(message.contains("Value is too long"));
+    }
+}
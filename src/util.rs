@@ -0,0 +1,54 @@
+// `N` bytes of `data` starting at `offset`, None when the buffer is too short. Use it instead
+// of indexing or `try_into().unwrap()` when decoding values received from a device.
+pub fn read_array<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    let end = offset.checked_add(N)?;
+    data.get(offset..end)?.try_into().ok()
+}
+
+// Path-loss exponent of free space, indoors walls and bodies push the real value up to ~4
+const PATH_LOSS_EXPONENT: f64 = 2.0;
+
+// Rough distance in metres from a received `rssi` and the `tx_power` a device advertises (its
+// RSSI at 1 m), using the log-distance path-loss model. Only a coarse estimate: a single reading
+// easily varies by several dBm, so prefer estimate_distance_smoothed for anything displayed.
+pub fn estimate_distance(rssi: i16, tx_power: i8) -> f64 {
+    distance_from(rssi as f64, tx_power)
+}
+
+// estimate_distance over the average of a sliding window of RSSI readings, None when empty
+pub fn estimate_distance_smoothed(rssi_window: &[i16], tx_power: i8) -> Option<f64> {
+    if rssi_window.is_empty() {
+        return None;
+    }
+    let sum: f64 = rssi_window.iter().map(|rssi| *rssi as f64).sum();
+    Some(distance_from(sum / rssi_window.len() as f64, tx_power))
+}
+
+fn distance_from(rssi: f64, tx_power: i8) -> f64 {
+    10f64.powf((tx_power as f64 - rssi) / (10.0 * PATH_LOSS_EXPONENT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_buffer_yields_none() {
+        let data = [0x01, 0x02, 0x03];
+        assert_eq!(read_array::<2>(&data, 1), Some([0x02, 0x03]));
+        assert_eq!(read_array::<2>(&data, 2), None);
+        assert_eq!(read_array::<4>(&data, 0), None);
+        assert_eq!(read_array::<1>(&data, usize::MAX), None);
+    }
+
+    #[test]
+    fn stronger_rssi_gives_a_smaller_distance() {
+        let tx_power = -59;
+        assert!((estimate_distance(-59, tx_power) - 1.0).abs() < 1e-9);
+        assert!(estimate_distance(-50, tx_power) < estimate_distance(-70, tx_power));
+        let near = estimate_distance_smoothed(&[-48, -52], tx_power).unwrap();
+        let far = estimate_distance_smoothed(&[-68, -72], tx_power).unwrap();
+        assert!(near < far);
+        assert_eq!(estimate_distance_smoothed(&[], tx_power), None);
+    }
+}